@@ -0,0 +1,209 @@
+pub mod gateway;
+pub mod oidc;
+pub mod rules;
+pub mod throttle;
+pub mod tokens;
+pub mod users;
+
+use aws_sdk_s3::Client;
+use data_encoding::BASE64;
+use lambda_http::Request;
+use serde_json::Value;
+use crate::auth::gateway::GatewayIdentities;
+use crate::auth::oidc::OidcIssuers;
+use crate::auth::rules::{Action, Rules};
+use crate::auth::throttle::Throttle;
+use crate::auth::users::UserStore;
+use crate::cfg::{AuthMode, ConfigError, MavenConfig, PrivatePaths};
+use crate::storage;
+
+// What the client sent in its Authorization header
+pub enum Authorization {
+	Missing,
+	Invalid,
+	Basic(BasicCredentials),
+	Bearer(String)
+}
+
+// Why a request didn't get an identity, no credentials at all gets a challenge instead of a flat no
+pub enum AuthFailure {
+	Missing,
+	Invalid,
+	// too many wrong credentials lately, with the seconds until they can try again
+	Throttled(i64)
+}
+
+// Someone who proved who they are, and what they can do because of it
+// Tokens narrow down what their owner can do with a scope of their own
+pub struct Identity {
+	pub name: String,
+	pub rules: Rules,
+	pub scope: Option<Rules>,
+	pub token: Option<String>
+}
+
+impl Identity {
+	pub fn can(&self, action: Action, path: &str) -> bool {
+		self.rules.allows(action, path) && self.scope.as_ref().is_none_or(|it| { it.allows(action, path) })
+	}
+}
+
+// Who is reading, if anyone, and so which private paths they get to see
+pub struct ReadAccess {
+	pub private_paths: PrivatePaths,
+	pub identity: Option<Identity>
+}
+
+impl ReadAccess {
+	pub fn can_read(&self, path: &str) -> bool {
+		!self.private_paths.is_private(path) || self.identity.as_ref().is_some_and(|it| { it.can(Action::Read, path) })
+	}
+
+	// Whether the response depends on who asked, those can't be cached for everyone
+	pub fn is_personal(&self, path: &str) -> bool {
+		self.identity.is_some() || self.private_paths.is_private(path)
+	}
+}
+
+pub struct BasicCredentials {
+	pub username: String,
+	pub password: String
+}
+
+// Only reads the header, checking the credentials is up to whoever needs them
+pub fn read_authorization(request: &Request) -> Authorization {
+	let header = match request.headers().get("Authorization").and_then(|it| { it.to_str().ok() }) {
+		None => { return Authorization::Missing }
+		Some(header) => { header }
+	};
+
+	let encoded = match header.split_once(' ') {
+		Some((scheme, token)) if scheme.eq_ignore_ascii_case("Bearer") => { return Authorization::Bearer(String::from(token.trim())) }
+		Some((scheme, encoded)) if scheme.eq_ignore_ascii_case("Basic") => { encoded.trim() }
+		_ => { return Authorization::Invalid }
+	};
+
+	// passwords can contain ':' but usernames can't, so split on the first one
+	let decoded = BASE64.decode(encoded.as_bytes()).ok().and_then(|it| { String::from_utf8(it).ok() });
+	match decoded.as_deref().and_then(|it| { it.split_once(':') }) {
+		None => { Authorization::Invalid }
+		Some((username, password)) => {
+			Authorization::Basic(BasicCredentials {
+				username: String::from(username),
+				password: String::from(password)
+			})
+		}
+	}
+}
+
+// Everyone who can prove who they are, loaded once per instance
+pub struct Authenticator {
+	pub users: UserStore,
+	pub oidc: OidcIssuers,
+	pub gateway: GatewayIdentities,
+	pub throttle: Throttle
+}
+
+impl Authenticator {
+	// Fails on anything wrong with the users, issuers or gateway rules, the same way a broken config does
+	pub async fn load(s3_client: &Client, maven_config: &MavenConfig) -> Result<Authenticator, ConfigError> {
+		let mut problems = Vec::new();
+		let authenticator = Authenticator {
+			users: UserStore::load(s3_client, maven_config, &mut problems).await,
+			oidc: OidcIssuers::load(s3_client, maven_config, &mut problems).await,
+			gateway: GatewayIdentities::load(s3_client, maven_config, &mut problems).await,
+			throttle: Throttle::new()
+		};
+
+		if problems.is_empty() {
+			Ok(authenticator)
+		} else {
+			Err(ConfigError { problems })
+		}
+	}
+
+	// Works out who is behind the request, from the gateway or the credentials it carries depending on AUTH_MODE
+	pub async fn authenticate(&self, request: &Request, s3_client: &Client, maven_config: &MavenConfig) -> Result<Identity, AuthFailure> {
+		match maven_config.auth_mode {
+			AuthMode::Function => { self.authenticate_header(request, s3_client, maven_config, true).await }
+			AuthMode::Gateway => { self.gateway.authenticate(request, maven_config) }
+			AuthMode::Either => {
+				match self.gateway.authenticate(request, maven_config) {
+					Err(AuthFailure::Missing) => { self.authenticate_header(request, s3_client, maven_config, true).await }
+					result => { result }
+				}
+			}
+		}
+	}
+
+	// Only a user's own password, for managing their tokens so a leaked token can't mint more
+	pub async fn authenticate_owner(&self, request: &Request, s3_client: &Client, maven_config: &MavenConfig) -> Result<Identity, AuthFailure> {
+		self.authenticate_header(request, s3_client, maven_config, false).await
+	}
+
+	// API tokens and JWTs work as a bearer token or in place of the password, since maven and gradle only know basic auth
+	// Wrong credentials count against the source ip, and the username when it came with a password
+	async fn authenticate_header(&self, request: &Request, s3_client: &Client, maven_config: &MavenConfig, allow_tokens: bool) -> Result<Identity, AuthFailure> {
+		let authorization = read_authorization(request);
+		let username = match &authorization {
+			Authorization::Missing => { return Err(AuthFailure::Missing) }
+			Authorization::Basic(credentials) if !is_token_like(&credentials.password) => { Some(credentials.username.as_str()) }
+			_ => { None }
+		};
+		let keys = throttle::keys(request, username);
+		if let Some(retry_after) = self.throttle.retry_after(s3_client, maven_config, &keys).await {
+			return Err(AuthFailure::Throttled(retry_after))
+		}
+
+		let identity = match &authorization {
+			Authorization::Missing | Authorization::Invalid => { None }
+			Authorization::Bearer(token) if allow_tokens => { self.authenticate_token(s3_client, maven_config, token).await }
+			Authorization::Basic(credentials) if is_token_like(&credentials.password) => {
+				if allow_tokens { self.authenticate_token(s3_client, maven_config, &credentials.password).await } else { None }
+			}
+			Authorization::Basic(credentials) => {
+				let identity = self.users.authenticate(&credentials.username, &credentials.password);
+				if identity.is_none() {
+					tracing::info!("User \"{}\" failed to authenticate", credentials.username.escape_debug());
+				}
+				identity
+			}
+			Authorization::Bearer(_) => { None }
+		};
+
+		match identity {
+			None => {
+				self.throttle.record_failure(s3_client, maven_config, &keys).await;
+				Err(AuthFailure::Invalid)
+			}
+			Some(identity) => {
+				// the username is always the first key, the ip stays counted so one good account can't reset it
+				if username.is_some() {
+					self.throttle.clear(s3_client, maven_config, &keys[0]).await;
+				}
+				Ok(identity)
+			}
+		}
+	}
+
+	async fn authenticate_token(&self, s3_client: &Client, maven_config: &MavenConfig, token: &str) -> Option<Identity> {
+		if oidc::is_jwt(token) {
+			self.oidc.authenticate(token)
+		} else {
+			tokens::authenticate(s3_client, maven_config, &self.users, token).await
+		}
+	}
+}
+
+fn is_token_like(password: &str) -> bool {
+	tokens::is_token(password) || oidc::is_jwt(password)
+}
+
+// Text config like the users file, from its section of the config (or the file standing in for it) or an object in the bucket
+// A missing object just means the feature isn't used
+pub async fn read_source(s3_client: &Client, maven_config: &MavenConfig, section: Option<&Value>, object: &str) -> Option<String> {
+	match section {
+		Some(section) => { Some(section.to_string()) }
+		None => { storage::get_object_text(s3_client, &maven_config.bucket_name, object).await }
+	}
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use aws_sdk_s3::Client;
+use lambda_http::{Request, RequestExt};
+use lambda_http::request::RequestContext;
+use serde::Deserialize;
+use crate::auth;
+use crate::auth::{AuthFailure, Identity};
+use crate::auth::oidc::claim_text;
+use crate::auth::rules::{ClaimRule, ClaimRules};
+use crate::cfg::MavenConfig;
+
+// {"rules": [
+//     {"claims": {"scope": "*maven:write*"}, "grants": [{"paths": ["**"], "actions": ["read", "write"]}]},
+//     {"claims": {"clientCert.subjectDn": "CN=ci.example.com*"}, "grants": [...]}
+// ]}
+#[derive(Deserialize)]
+struct GatewayFile {
+	rules: Vec<ClaimRule>
+}
+
+// Callers api gateway already checked, by a jwt or lambda authorizer or a client certificate
+// We only decide what they can do, that they are who they say is up to the gateway
+pub struct GatewayIdentities {
+	rules: ClaimRules
+}
+
+impl GatewayIdentities {
+	pub async fn load(s3_client: &Client, maven_config: &MavenConfig, problems: &mut Vec<String>) -> GatewayIdentities {
+		let source = auth::read_source(s3_client, maven_config, maven_config.sections.gateway.as_ref(), &maven_config.gateway_rules_object).await;
+		let rules = match source.map(|it| { serde_json::from_str::<GatewayFile>(&it) }) {
+			None => { Vec::new() }
+			Some(Ok(file)) => { file.rules }
+			Some(Err(err)) => {
+				problems.push(format!("The gateway rules are invalid. {err}"));
+				Vec::new()
+			}
+		};
+
+		let rules = ClaimRules::new(&rules).unwrap_or_else(|err| {
+			problems.push(format!("The gateway rules are invalid. {err}"));
+			ClaimRules::new(&[]).expect("No rules are always valid")
+		});
+		GatewayIdentities { rules }
+	}
+
+	// Missing when the gateway didn't vouch for anyone, invalid when it did but no rule gives them anything
+	pub fn authenticate(&self, request: &Request, maven_config: &MavenConfig) -> Result<Identity, AuthFailure> {
+		let claims = claims(request).ok_or(AuthFailure::Missing)?;
+		let name = claims.get(&maven_config.gateway_name_claim)
+			.or_else(|| { claims.get("clientCert.subjectDn") })
+			.cloned()
+			.unwrap_or_default();
+
+		match self.rules.find(&claims) {
+			None => {
+				tracing::info!("No gateway rule matches \"{}\"", name.escape_debug());
+				Err(AuthFailure::Invalid)
+			}
+			Some(rules) => {
+				Ok(Identity {
+					name,
+					rules: rules.clone(),
+					scope: None,
+					token: None
+				})
+			}
+		}
+	}
+}
+
+// Everything the gateway says about the caller, None if it says nothing
+// Certificate fields are under "clientCert." so they can't be mistaken for a claim an authorizer made up
+fn claims(request: &Request) -> Option<HashMap<String, String>> {
+	let context = match request.request_context() {
+		RequestContext::ApiGatewayV2(context) => { context }
+		_ => { return None }
+	};
+
+	let mut claims = HashMap::new();
+	if let Some(authorizer) = context.authorizer {
+		if let Some(jwt) = authorizer.jwt {
+			claims.extend(jwt.claims);
+		}
+		claims.extend(authorizer.lambda.into_iter().map(|(claim, value)| { (claim, claim_text(value)) }));
+	}
+	if let Some(cert) = context.authentication.and_then(|it| { it.client_cert }) {
+		let fields = [
+			("clientCert.subjectDn", cert.subject_dn),
+			("clientCert.issuerDn", cert.issuer_dn),
+			("clientCert.serialNumber", cert.serial_number)
+		];
+		for (claim, value) in fields {
+			if let Some(value) = value {
+				claims.insert(String::from(claim), value);
+			}
+		}
+	}
+
+	if claims.is_empty() { None } else { Some(claims) }
+}
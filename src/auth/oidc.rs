@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use aws_sdk_s3::Client;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use jsonwebtoken::jwk::JwkSet;
+use serde::Deserialize;
+use serde_json::Value;
+use crate::auth;
+use crate::auth::Identity;
+use crate::auth::rules::{ClaimRule, ClaimRules};
+use crate::cfg::MavenConfig;
+use crate::storage;
+
+// {"issuers": [{
+//     "issuer": "https://token.actions.githubusercontent.com",
+//     "audience": "https://maven.example.com",
+//     "jwksFile": "github-jwks.json",
+//     "rules": [{"claims": {"repository": "example/lib", "ref": "refs/heads/*"}, "grants": [...]}]
+// }]}
+// Keys come from the config (inline, a file or an object in the bucket) rather than the issuer, so nothing here needs the network
+#[derive(Deserialize)]
+struct OidcFile {
+	issuers: Vec<IssuerEntry>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssuerEntry {
+	issuer: String,
+	audience: String,
+	jwks: Option<JwkSet>,
+	jwks_file: Option<String>,
+	jwks_object: Option<String>,
+	rules: Vec<ClaimRule>
+}
+
+struct Issuer {
+	issuer: String,
+	audience: String,
+	keys: JwkSet,
+	rules: ClaimRules
+}
+
+impl Issuer {
+	// Why it can't be used otherwise
+	fn new(entry: IssuerEntry, keys: JwkSet) -> Result<Issuer, String> {
+		let rules = ClaimRules::new(&entry.rules)
+			.map_err(|err| { format!("Rules of issuer \"{}\" are invalid. {err}", entry.issuer) })?;
+
+		Ok(Issuer {
+			issuer: entry.issuer,
+			audience: entry.audience,
+			keys,
+			rules
+		})
+	}
+}
+
+// Issuers whose tokens we accept, loaded once per instance
+pub struct OidcIssuers {
+	issuers: Vec<Issuer>
+}
+
+impl OidcIssuers {
+	// Anything wrong with an issuer is added to the problems, one that was left out would have its tokens refused
+	pub async fn load(s3_client: &Client, maven_config: &MavenConfig, problems: &mut Vec<String>) -> OidcIssuers {
+		let source = auth::read_source(s3_client, maven_config, maven_config.sections.oidc.as_ref(), &maven_config.oidc_object).await;
+		let file: OidcFile = match source.map(|it| { serde_json::from_str(&it) }) {
+			None => { return OidcIssuers { issuers: Vec::new() } }
+			Some(Ok(file)) => { file }
+			Some(Err(err)) => {
+				problems.push(format!("The OIDC config is invalid. {err}"));
+				return OidcIssuers { issuers: Vec::new() }
+			}
+		};
+
+		let mut issuers = Vec::new();
+		for entry in file.issuers {
+			let issuer = match load_keys(s3_client, maven_config, &entry).await {
+				Err(err) => { Err(err) }
+				Ok(keys) => { Issuer::new(entry, keys) }
+			};
+			match issuer {
+				Err(err) => { problems.push(err) }
+				Ok(issuer) => { issuers.push(issuer) }
+			}
+		}
+
+		tracing::info!("Accepting tokens from {} OIDC issuers", issuers.len());
+		OidcIssuers { issuers }
+	}
+
+	// Verifies the token against whichever issuer has the key it was signed with
+	// Named after the subject, with the grants of the first rule that matches its claims
+	pub fn authenticate(&self, token: &str) -> Option<Identity> {
+		let header = jsonwebtoken::decode_header(token).ok()?;
+		// only asymmetric keys make sense from a jwks, and this keeps an hmac token from being checked against a public key
+		if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+			return None
+		}
+
+		let kid = header.kid?;
+		let (issuer, key) = self.issuers.iter()
+			.find_map(|it| { it.keys.find(&kid).map(|key| { (it, key) }) })?;
+
+		let mut validation = Validation::new(header.alg);
+		validation.set_issuer(&[&issuer.issuer]);
+		validation.set_audience(&[&issuer.audience]);
+		validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+		let claims: HashMap<String, String> = match jsonwebtoken::decode::<HashMap<String, Value>>(token, &DecodingKey::from_jwk(key).ok()?, &validation) {
+			Ok(data) => { data.claims.into_iter().map(|(claim, value)| { (claim, claim_text(value)) }).collect() }
+			Err(err) => {
+				tracing::info!("Rejected a token from \"{}\". {err}", issuer.issuer);
+				return None
+			}
+		};
+		let subject = claims.get("sub").map(String::as_str).unwrap_or_default();
+
+		match issuer.rules.find(&claims) {
+			None => {
+				tracing::info!("No rule of \"{}\" matches the token of \"{subject}\"", issuer.issuer);
+				None
+			}
+			Some(rules) => {
+				Some(Identity {
+					name: String::from(subject),
+					rules: rules.clone(),
+					scope: None,
+					token: None
+				})
+			}
+		}
+	}
+}
+
+// Looks enough like a jwt to try it, "eyJ" is how every base64 encoded json header starts
+pub fn is_jwt(value: &str) -> bool {
+	value.starts_with("eyJ") && value.matches('.').count() == 2
+}
+
+async fn load_keys(s3_client: &Client, maven_config: &MavenConfig, entry: &IssuerEntry) -> Result<JwkSet, String> {
+	let keys = match (&entry.jwks, &entry.jwks_file, &entry.jwks_object) {
+		(Some(keys), _, _) => { keys.clone() }
+		(None, Some(file), _) => {
+			let source = std::fs::read_to_string(file)
+				.map_err(|err| { format!("JWKS file \"{file}\" of issuer \"{}\" can't be read. {err}", entry.issuer) })?;
+			parse_keys(entry, &source)?
+		}
+		(None, None, Some(object)) => {
+			let source = storage::get_object_text(s3_client, &maven_config.bucket_name, object).await
+				.ok_or_else(|| { format!("JWKS object \"{object}\" of issuer \"{}\" can't be read.", entry.issuer) })?;
+			parse_keys(entry, &source)?
+		}
+		(None, None, None) => { return Err(format!("Issuer \"{}\" has no keys, give it jwks, jwksFile or jwksObject.", entry.issuer)) }
+	};
+
+	if keys.keys.is_empty() {
+		return Err(format!("JWKS of issuer \"{}\" has no keys in it.", entry.issuer))
+	}
+	Ok(keys)
+}
+
+fn parse_keys(entry: &IssuerEntry, source: &str) -> Result<JwkSet, String> {
+	serde_json::from_str(source).map_err(|err| { format!("JWKS of issuer \"{}\" is invalid. {err}", entry.issuer) })
+}
+
+// Claims are matched as text, so "true" and 123 work in the rules too
+pub fn claim_text(value: Value) -> String {
+	match value {
+		Value::String(text) => { text }
+		other => { other.to_string() }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use jsonwebtoken::{EncodingKey, Header};
+	use serde_json::json;
+	use crate::auth::rules::Action;
+	use crate::util::now;
+	use super::*;
+
+	const ISSUER: &str = "https://token.actions.githubusercontent.com";
+	const AUDIENCE: &str = "https://maven.example.com";
+	const ARTIFACT: &str = "com/example/lib/1.0/lib-1.0.jar";
+
+	// Tags can publish, any other ref of the repository can only read
+	fn issuers() -> OidcIssuers {
+		let entry: IssuerEntry = serde_json::from_value(json!({
+			"issuer": ISSUER,
+			"audience": AUDIENCE,
+			"jwksFile": concat!(env!("CARGO_MANIFEST_DIR"), "/src/auth/testdata/oidc-jwks.json"),
+			"rules": [
+				{"claims": {"repository": "example/lib", "ref": "refs/tags/*"}, "grants": [{"paths": ["com/example/lib/**"], "actions": ["read", "write"]}]},
+				{"claims": {"repository": "example/lib"}, "grants": [{"paths": ["com/example/lib/**"], "actions": ["read"]}]}
+			]
+		})).unwrap();
+		let keys = std::fs::read_to_string(entry.jwks_file.as_deref().unwrap()).unwrap();
+		let issuer = Issuer::new(entry, serde_json::from_str(&keys).unwrap()).unwrap();
+		OidcIssuers { issuers: vec![issuer] }
+	}
+
+	fn claims() -> Value {
+		json!({
+			"iss": ISSUER,
+			"aud": AUDIENCE,
+			"sub": "repo:example/lib:ref:refs/tags/v1.0",
+			"exp": now() + 300,
+			"repository": "example/lib",
+			"ref": "refs/tags/v1.0"
+		})
+	}
+
+	fn sign(claims: &Value) -> String {
+		let mut header = Header::new(Algorithm::RS256);
+		header.kid = Some(String::from("test-key"));
+		let key = EncodingKey::from_rsa_pem(include_bytes!("testdata/oidc-key.pem")).unwrap();
+		jsonwebtoken::encode(&header, claims, &key).unwrap()
+	}
+
+	fn with(claim: &str, value: Value) -> Value {
+		let mut claims = claims();
+		claims[claim] = value;
+		claims
+	}
+
+	#[test]
+	fn accepts_a_valid_token() {
+		let token = sign(&claims());
+		assert!(is_jwt(&token));
+
+		let identity = issuers().authenticate(&token).expect("token should be accepted");
+		assert_eq!(identity.name, "repo:example/lib:ref:refs/tags/v1.0");
+		assert!(identity.can(Action::Write, ARTIFACT));
+		assert!(!identity.can(Action::Write, "com/example/other/1.0/other-1.0.jar"));
+		assert!(!identity.can(Action::Delete, ARTIFACT));
+	}
+
+	#[test]
+	fn rejects_an_expired_token() {
+		assert!(issuers().authenticate(&sign(&with("exp", json!(now() - 3600)))).is_none());
+	}
+
+	#[test]
+	fn rejects_another_issuer() {
+		assert!(issuers().authenticate(&sign(&with("iss", json!("https://attacker.example.com")))).is_none());
+	}
+
+	#[test]
+	fn rejects_another_audience() {
+		assert!(issuers().authenticate(&sign(&with("aud", json!("https://other.example.com")))).is_none());
+	}
+
+	#[test]
+	fn rejects_hmac_tokens() {
+		let mut header = Header::new(Algorithm::HS256);
+		header.kid = Some(String::from("test-key"));
+		let token = jsonwebtoken::encode(&header, &claims(), &EncodingKey::from_secret(b"secret")).unwrap();
+		assert!(issuers().authenticate(&token).is_none());
+	}
+
+	#[test]
+	fn maps_claims_to_grants() {
+		let issuers = issuers();
+
+		// a branch of the same repository only gets the second rule
+		let branch = issuers.authenticate(&sign(&with("ref", json!("refs/heads/main")))).expect("branch should match a rule");
+		assert!(branch.can(Action::Read, ARTIFACT));
+		assert!(!branch.can(Action::Write, ARTIFACT));
+
+		// and another repository matches no rule at all
+		assert!(issuers.authenticate(&sign(&with("repository", json!("example/other")))).is_none());
+	}
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+	Read,
+	Write,
+	Delete
+}
+
+impl Display for Action {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Action::Read => { write!(f, "read") }
+			Action::Write => { write!(f, "write") }
+			Action::Delete => { write!(f, "delete") }
+		}
+	}
+}
+
+// {"paths": ["com/example/teamA/**"], "actions": ["read", "write"]}
+// '*' stays inside one segment, '**' crosses any number of them
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Grant {
+	pub paths: Vec<String>,
+	pub actions: Vec<Action>
+}
+
+// Everything someone is allowed to do, a path is allowed if any grant covers it
+#[derive(Clone)]
+pub struct Rules {
+	grants: Vec<(GlobSet, Vec<Action>)>
+}
+
+impl Rules {
+	pub fn new(grants: &[Grant]) -> Result<Rules, globset::Error> {
+		let mut compiled = Vec::with_capacity(grants.len());
+		for grant in grants {
+			let mut builder = GlobSetBuilder::new();
+			for path in &grant.paths {
+				builder.add(glob(path)?);
+			}
+			compiled.push((builder.build()?, grant.actions.clone()));
+		}
+		Ok(Rules { grants: compiled })
+	}
+
+	// What users without any grants get, so setups from before rules existed keep working
+	pub fn everything() -> Rules {
+		Rules::new(&[Grant {
+			paths: vec![String::from("**")],
+			actions: vec![Action::Read, Action::Write, Action::Delete]
+		}]).expect("Built in rules must be valid")
+	}
+
+	pub fn allows(&self, action: Action, path: &str) -> bool {
+		self.grants.iter().any(|(paths, actions)| { actions.contains(&action) && paths.is_match(path) })
+	}
+}
+
+// {"claims": {"repository": "example/lib", "ref": "refs/heads/*"}, "grants": [...]}
+// For callers known by what someone else says about them, rather than by a name we have grants for
+#[derive(Deserialize)]
+pub struct ClaimRule {
+	pub claims: HashMap<String, String>,
+	pub grants: Vec<Grant>
+}
+
+// The first rule whose claims all match decides what the caller can do
+// Claim values are globs, but a '*' crosses '/' here so "refs/heads/*" covers every branch
+pub struct ClaimRules {
+	rules: Vec<(Vec<(String, GlobMatcher)>, Rules)>
+}
+
+impl ClaimRules {
+	pub fn new(rules: &[ClaimRule]) -> Result<ClaimRules, globset::Error> {
+		let mut compiled = Vec::with_capacity(rules.len());
+		for rule in rules {
+			let mut claims = Vec::with_capacity(rule.claims.len());
+			for (claim, pattern) in &rule.claims {
+				claims.push((claim.clone(), Glob::new(pattern)?.compile_matcher()));
+			}
+			compiled.push((claims, Rules::new(&rule.grants)?));
+		}
+		Ok(ClaimRules { rules: compiled })
+	}
+
+	pub fn find(&self, claims: &HashMap<String, String>) -> Option<&Rules> {
+		self.rules.iter()
+			.find(|(patterns, _)| {
+				patterns.iter().all(|(claim, pattern)| { claims.get(claim).is_some_and(|value| { pattern.is_match(value) }) })
+			})
+			.map(|(_, rules)| { rules })
+	}
+}
+
+fn glob(path: &str) -> Result<Glob, globset::Error> {
+	GlobBuilder::new(path.trim_start_matches('/'))
+		.literal_separator(true)
+		.build()
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::ByteStream;
+use data_encoding::HEXLOWER;
+use lambda_http::{Request, RequestExt};
+use lambda_http::request::RequestContext;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::cfg::MavenConfig;
+use crate::storage;
+use crate::util::now;
+
+pub const THROTTLE_PREFIX: &str = ".maven/throttle/";
+
+// Failed attempts since the start of the current window
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct Failures {
+	count: u32,
+	since: i64
+}
+
+// Counts failed logins per username and per source ip, and turns callers away once they've had too many
+// Each instance only knows its own failures unless LOGIN_FAILURES_SHARED keeps them in the bucket
+pub struct Throttle {
+	failures: Mutex<HashMap<String, Failures>>
+}
+
+impl Throttle {
+	pub fn new() -> Throttle {
+		Throttle { failures: Mutex::new(HashMap::new()) }
+	}
+
+	// Seconds until the caller can try again, if any of the keys has failed too often lately
+	pub async fn retry_after(&self, s3_client: &Client, maven_config: &MavenConfig, keys: &[String]) -> Option<i64> {
+		if maven_config.login_max_failures == 0 {
+			return None
+		}
+
+		let mut retry_after = None;
+		for key in keys {
+			if let Some(failures) = self.get(s3_client, maven_config, key).await {
+				if failures.count >= maven_config.login_max_failures {
+					let seconds = failures.since + maven_config.login_lockout_seconds - now();
+					retry_after = Some(retry_after.unwrap_or(0).max(seconds));
+				}
+			}
+		}
+		retry_after
+	}
+
+	pub async fn record_failure(&self, s3_client: &Client, maven_config: &MavenConfig, keys: &[String]) {
+		if maven_config.login_max_failures == 0 {
+			return
+		}
+
+		for key in keys {
+			let failures = match self.get(s3_client, maven_config, key).await {
+				None => { Failures { count: 1, since: now() } }
+				Some(failures) => { Failures { count: failures.count + 1, since: failures.since } }
+			};
+			if failures.count == maven_config.login_max_failures {
+				tracing::warn!("Too many failed logins from {key}, refusing it for {} seconds", maven_config.login_lockout_seconds);
+			}
+			self.set(s3_client, maven_config, key, Some(failures)).await;
+		}
+	}
+
+	// A good login forgets the failures before it, a typo shouldn't count against someone forever
+	pub async fn clear(&self, s3_client: &Client, maven_config: &MavenConfig, key: &str) {
+		if maven_config.login_max_failures == 0 {
+			return
+		}
+		if self.get(s3_client, maven_config, key).await.is_some() {
+			self.set(s3_client, maven_config, key, None).await;
+		}
+	}
+
+	// Nothing once the window is over, old failures don't count anymore
+	async fn get(&self, s3_client: &Client, maven_config: &MavenConfig, key: &str) -> Option<Failures> {
+		let failures = if maven_config.login_failures_shared {
+			let obj = storage::get_resource(s3_client, maven_config, &object_key(key)).await?;
+			let bytes = obj.body.collect().await.ok()?.into_bytes();
+			serde_json::from_slice(&bytes).ok()?
+		} else {
+			*self.failures.lock().unwrap().get(key)?
+		};
+
+		if failures.since + maven_config.login_lockout_seconds <= now() { None } else { Some(failures) }
+	}
+
+	// Counting is best effort, a lost update only means someone gets a guess more
+	async fn set(&self, s3_client: &Client, maven_config: &MavenConfig, key: &str, failures: Option<Failures>) {
+		if !maven_config.login_failures_shared {
+			let mut local = self.failures.lock().unwrap();
+			// forget whoever's window is over, so guessing through made up usernames doesn't fill the memory
+			local.retain(|_, it| { it.since + maven_config.login_lockout_seconds > now() });
+			match failures {
+				None => { local.remove(key); }
+				Some(failures) => { local.insert(String::from(key), failures); }
+			}
+			return
+		}
+
+		let error = match failures {
+			None => {
+				s3_client.delete_object()
+					.bucket(&maven_config.bucket_name)
+					.key(object_key(key))
+					.send().await
+					.err().map(|err| { err.to_string() })
+			}
+			Some(failures) => {
+				s3_client.put_object()
+					.bucket(&maven_config.bucket_name)
+					.key(object_key(key))
+					.content_type("application/json")
+					.body(ByteStream::from(serde_json::to_vec(&failures).unwrap_or_default()))
+					.send().await
+					.err().map(|err| { err.to_string() })
+			}
+		};
+		if let Some(err) = error {
+			tracing::error!("Failed to store failed logins of {key}. {err}");
+		}
+	}
+}
+
+// The keys a failed login counts against, the username only when there was a password to guess
+pub fn keys(request: &Request, username: Option<&str>) -> Vec<String> {
+	let mut keys = Vec::new();
+	if let Some(username) = username {
+		keys.push(format!("user \"{}\"", username.escape_debug()));
+	}
+	if let RequestContext::ApiGatewayV2(context) = request.request_context() {
+		if let Some(ip) = context.http.source_ip {
+			keys.push(format!("ip {ip}"));
+		}
+	}
+	keys
+}
+
+// Hashed so whatever someone typed as their username doesn't end up as an object key
+fn object_key(key: &str) -> String {
+	format!("{THROTTLE_PREFIX}{}.json", HEXLOWER.encode(&Sha256::digest(key.as_bytes())))
+}
@@ -0,0 +1,180 @@
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::ByteStream;
+use data_encoding::HEXLOWER;
+use lambda_runtime::Error;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use crate::auth::Identity;
+use crate::auth::rules::{Grant, Rules};
+use crate::auth::users::UserStore;
+use crate::cfg::MavenConfig;
+use crate::storage;
+use crate::util::now;
+
+pub const TOKEN_PREFIX: &str = ".maven/tokens/";
+
+// Tokens look like "mvn_<id>_<secret>", the id finds the stored token and only a hash of the secret is kept
+const TOKEN_MARKER: &str = "mvn_";
+const DEFAULT_LIFETIME_DAYS: i64 = 90;
+const MAX_LIFETIME_DAYS: i64 = 365;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredToken {
+	pub id: String,
+	pub owner: String,
+	pub name: String,
+	pub hash: String,
+	pub created: i64,
+	pub expires: i64,
+	// everything the owner can do when left out
+	pub grants: Option<Vec<Grant>>
+}
+
+// The body of a POST to api/tokens
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewToken {
+	pub name: String,
+	pub expires_in_days: Option<i64>,
+	pub grants: Option<Vec<Grant>>
+}
+
+impl NewToken {
+	// What's wrong with the request, if anything
+	pub fn problem(&self) -> Option<String> {
+		let days = self.expires_in_days.unwrap_or(DEFAULT_LIFETIME_DAYS);
+		if !(1..=MAX_LIFETIME_DAYS).contains(&days) {
+			return Some(format!("Tokens have to expire within 1 to {MAX_LIFETIME_DAYS} days."))
+		}
+		if let Some(Err(err)) = self.grants.as_deref().map(Rules::new) {
+			return Some(format!("Invalid grants. {err}"))
+		}
+		None
+	}
+}
+
+impl StoredToken {
+	pub fn is_expired(&self) -> bool {
+		self.expires <= now()
+	}
+}
+
+pub fn is_token(value: &str) -> bool {
+	value.starts_with(TOKEN_MARKER)
+}
+
+// The owner of the token, narrowed down to the token's own grants
+// Checked against the bucket every time so revoking takes effect right away
+pub async fn authenticate(s3_client: &Client, maven_config: &MavenConfig, users: &UserStore, token: &str) -> Option<Identity> {
+	let (id, secret) = token.strip_prefix(TOKEN_MARKER)?.split_once('_')?;
+	let stored = load(s3_client, maven_config, id).await?;
+
+	if !bool::from(hash(secret).as_bytes().ct_eq(stored.hash.as_bytes())) {
+		tracing::info!("Token {id} was used with the wrong secret");
+		return None
+	}
+	if stored.is_expired() {
+		tracing::info!("Token {id} of \"{}\" has expired", stored.owner);
+		return None
+	}
+
+	// tokens die with their owner
+	let mut identity = users.find(&stored.owner)?;
+	identity.scope = match &stored.grants {
+		None => { None }
+		Some(grants) => { Some(Rules::new(grants).ok()?) }
+	};
+	identity.token = Some(stored.id);
+	Some(identity)
+}
+
+// Returns the stored token and the only copy of the full token string there will ever be
+pub async fn create(s3_client: &Client, maven_config: &MavenConfig, owner: &str, new_token: NewToken) -> Result<(StoredToken, String), Error> {
+	let id = random_hex(8);
+	let secret = random_hex(32);
+	let created = now();
+
+	let stored = StoredToken {
+		id: id.clone(),
+		owner: String::from(owner),
+		name: new_token.name,
+		hash: hash(&secret),
+		created,
+		expires: created + new_token.expires_in_days.unwrap_or(DEFAULT_LIFETIME_DAYS) * 24 * 60 * 60,
+		grants: new_token.grants
+	};
+
+	s3_client.put_object()
+		.bucket(&maven_config.bucket_name)
+		.key(token_key(&id))
+		.content_type("application/json")
+		.body(ByteStream::from(serde_json::to_vec(&stored)?))
+		.send().await?;
+	tracing::info!("Created token {id} for \"{owner}\"");
+	Ok((stored, format!("{TOKEN_MARKER}{id}_{secret}")))
+}
+
+// Every token of one user, there shouldn't be enough tokens around for reading them all to matter
+pub async fn list(s3_client: &Client, maven_config: &MavenConfig, owner: &str) -> Result<Vec<StoredToken>, Error> {
+	let objects = storage::list_objects(s3_client, maven_config, TOKEN_PREFIX).await?;
+
+	let mut tokens = Vec::new();
+	for obj in objects {
+		let id = obj.key.as_deref()
+			.and_then(|it| { it.strip_prefix(TOKEN_PREFIX) })
+			.and_then(|it| { it.strip_suffix(".json") })
+			.unwrap_or_default();
+		if let Some(token) = load(s3_client, maven_config, id).await {
+			if token.owner == owner {
+				tokens.push(token);
+			}
+		}
+	}
+	tokens.sort_by_key(|it| { it.created });
+	Ok(tokens)
+}
+
+// False if there is no such token, or it belongs to someone else
+pub async fn revoke(s3_client: &Client, maven_config: &MavenConfig, owner: &str, id: &str) -> Result<bool, Error> {
+	match load(s3_client, maven_config, id).await {
+		Some(token) if token.owner == owner => {
+			s3_client.delete_object()
+				.bucket(&maven_config.bucket_name)
+				.key(token_key(id))
+				.send().await?;
+			tracing::info!("Revoked token {id} of \"{owner}\"");
+			Ok(true)
+		}
+		_ => { Ok(false) }
+	}
+}
+
+async fn load(s3_client: &Client, maven_config: &MavenConfig, id: &str) -> Option<StoredToken> {
+	// ids are always hex, anything else could point the key somewhere else
+	if id.is_empty() || !id.chars().all(|it| { it.is_ascii_hexdigit() }) {
+		return None
+	}
+
+	let obj = storage::get_resource(s3_client, maven_config, &token_key(id)).await?;
+	let bytes = obj.body.collect().await.ok()?.into_bytes();
+	serde_json::from_slice(&bytes).ok()
+}
+
+fn token_key(id: &str) -> String {
+	format!("{TOKEN_PREFIX}{id}.json")
+}
+
+// The secrets are random enough that a plain sha256 is all the hashing they need
+fn hash(secret: &str) -> String {
+	HEXLOWER.encode(&Sha256::digest(secret.as_bytes()))
+}
+
+fn random_hex(bytes: usize) -> String {
+	let mut buffer = vec![0u8; bytes];
+	OsRng.fill_bytes(&mut buffer);
+	HEXLOWER.encode(&buffer)
+}
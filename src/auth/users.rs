@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use aws_sdk_s3::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use crate::auth;
+use crate::auth::Identity;
+use crate::auth::rules::{Grant, Rules};
+use crate::cfg::MavenConfig;
+
+// Hash of a password nobody has, with the default parameters, checked against when the user doesn't exist
+const UNKNOWN_USER_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$nm2SpbFICiCYUbYRsYdnPA$DE66Z5RJapm2LpsnOnSpDBU8rx6r2oI5I/SrCVS/ZMA";
+
+// {"users": {"alice": {"password": "$argon2id$..."}, "ci": {"password": "$2b$12$...", "grants": [...]}}}
+// One entry per user, so rotating a credential only touches that user's line
+#[derive(Deserialize)]
+struct UsersFile {
+	users: HashMap<String, UserEntry>
+}
+
+#[derive(Deserialize)]
+struct UserEntry {
+	password: String,
+	// everything when left out
+	grants: Option<Vec<Grant>>
+}
+
+enum Secret {
+	Argon2(String),
+	Bcrypt(String),
+	// only the old UPLOAD_USERNAME/UPLOAD_PASSWORD pair, files must use hashes
+	Plain(String)
+}
+
+struct User {
+	secret: Secret,
+	rules: Rules
+}
+
+// Everyone that can authenticate, loaded once per instance
+pub struct UserStore {
+	users: HashMap<String, User>
+}
+
+impl UserStore {
+	// Anything wrong with the users is added to the problems, a user that was left out would just be locked out
+	pub async fn load(s3_client: &Client, maven_config: &MavenConfig, problems: &mut Vec<String>) -> UserStore {
+		let source = auth::read_source(s3_client, maven_config, maven_config.sections.users.as_ref(), &maven_config.users_object).await;
+
+		let mut users: HashMap<String, User> = HashMap::new();
+		if let Some(source) = source {
+			match serde_json::from_str::<UsersFile>(&source) {
+				Ok(file) => {
+					for (username, entry) in file.users {
+						let secret = match parse_hash(entry.password) {
+							None => {
+								problems.push(format!("Password of user \"{username}\" is not an argon2 or bcrypt hash."));
+								continue
+							}
+							Some(secret) => { secret }
+						};
+						let rules = match entry.grants.as_deref().map(Rules::new) {
+							None => { Rules::everything() }
+							Some(Ok(rules)) => { rules }
+							Some(Err(err)) => {
+								problems.push(format!("Grants of user \"{username}\" are invalid. {err}"));
+								continue
+							}
+						};
+						users.insert(username, User { secret, rules });
+					}
+				}
+				Err(err) => { problems.push(format!("The users file is invalid. {err}")) }
+			}
+		}
+
+		if let (Some(username), Some(password)) = (&maven_config.username, &maven_config.password) {
+			users.entry(username.clone()).or_insert_with(|| {
+				User {
+					secret: Secret::Plain(password.clone()),
+					rules: Rules::everything()
+				}
+			});
+		}
+
+		tracing::info!("Loaded {} users", users.len());
+		UserStore { users }
+	}
+
+	// Who the credentials belong to, None if they are wrong
+	pub fn authenticate(&self, username: &str, password: &str) -> Option<Identity> {
+		let valid = match self.users.get(username).map(|it| { &it.secret }) {
+			// still hash something, otherwise how quickly we say no tells which usernames exist
+			None => {
+				verify_argon2(UNKNOWN_USER_HASH, password);
+				false
+			}
+			Some(Secret::Argon2(hash)) => { verify_argon2(hash, password) }
+			Some(Secret::Bcrypt(hash)) => { bcrypt::verify(password, hash).unwrap_or(false) }
+			// comparing digests so the time doesn't depend on how long the password is either
+			Some(Secret::Plain(expected)) => { Sha256::digest(expected.as_bytes()).ct_eq(&Sha256::digest(password.as_bytes())).into() }
+		};
+
+		if valid { self.find(username) } else { None }
+	}
+
+	// Someone that was already authenticated another way, like through one of their tokens
+	pub fn find(&self, username: &str) -> Option<Identity> {
+		let user = self.users.get(username)?;
+		Some(Identity {
+			name: String::from(username),
+			rules: user.rules.clone(),
+			scope: None,
+			token: None
+		})
+	}
+}
+
+fn verify_argon2(hash: &str, password: &str) -> bool {
+	PasswordHash::new(hash)
+		.is_ok_and(|it| { Argon2::default().verify_password(password.as_bytes(), &it).is_ok() })
+}
+
+// Checked up front so a typo in the file shows up when the instance starts, not on the first upload
+fn parse_hash(hash: String) -> Option<Secret> {
+	if hash.starts_with("$argon2") {
+		PasswordHash::new(&hash).ok()?;
+		Some(Secret::Argon2(hash))
+	} else if hash.starts_with("$2") {
+		hash.parse::<bcrypt::HashParts>().ok()?;
+		Some(Secret::Bcrypt(hash))
+	} else {
+		None
+	}
+}
@@ -0,0 +1,66 @@
+use lambda_http::Request;
+use crate::util;
+use crate::util::query_param;
+
+// Shields style badges for a readme, /badge/com/example/lib.svg
+pub struct BadgeQuery {
+	pub artifact_directory: String,
+	pub label: String,
+	pub style: BadgeStyle,
+	pub color: Option<String>,
+	pub snapshots: bool
+}
+
+#[derive(Clone, Copy)]
+pub enum BadgeStyle {
+	Flat,
+	FlatSquare,
+	ForTheBadge
+}
+
+impl BadgeQuery {
+	// None if the path isn't "badge/<group path>/<artifact>.svg", or that isn't a directory anyone could read
+	pub fn from_request(request: &Request, request_path: &str) -> Option<BadgeQuery> {
+		let artifact_directory = util::artifact_directory(request_path.strip_prefix("badge/")?.strip_suffix(".svg")?)?;
+		if !artifact_directory.contains('/') {
+			return None
+		}
+
+		let style = match query_param(request, "style").as_deref() {
+			Some("flat-square") => { BadgeStyle::FlatSquare }
+			Some("for-the-badge") => { BadgeStyle::ForTheBadge }
+			_ => { BadgeStyle::Flat }
+		};
+
+		Some(BadgeQuery {
+			artifact_directory,
+			label: query_param(request, "label").unwrap_or(String::from("maven")),
+			style,
+			color: query_param(request, "color").and_then(|it| { parse_color(&it) }),
+			snapshots: query_param(request, "snapshots").is_some_and(|it| { it == "true" })
+		})
+	}
+}
+
+// Only named shields colors or bare hex, anything else could break out of the svg attribute
+fn parse_color(color: &str) -> Option<String> {
+	let named = match color {
+		"brightgreen" => { Some("#4c1") }
+		"green" => { Some("#97ca00") }
+		"yellow" => { Some("#dfb317") }
+		"orange" => { Some("#fe7d37") }
+		"red" => { Some("#e05d44") }
+		"blue" => { Some("#007ec6") }
+		"lightgrey" | "lightgray" => { Some("#9f9f9f") }
+		_ => { None }
+	};
+	if let Some(named) = named {
+		return Some(String::from(named))
+	}
+
+	let hex = color.trim_start_matches('#');
+	if (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|it| { it.is_ascii_hexdigit() }) {
+		return Some(format!("#{hex}"))
+	}
+	None
+}
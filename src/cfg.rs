@@ -1,3 +1,13 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use aws_config::SdkConfig;
+use aws_sdk_s3::Client;
+use serde::Serialize;
+use serde_json::Value;
+use crate::maven::layout;
+use crate::{secrets, storage};
+
 pub struct MavenConfig {
 	pub bucket_name: String,
 
@@ -5,36 +15,430 @@ pub struct MavenConfig {
 	pub indexing_max_keys: i32,
 
 	pub max_artifact_size: i64,
+	pub raw_path_prefixes: Vec<String>,
+	pub private_paths: PrivatePaths,
+
+	pub branding: Branding,
+	pub public_url: Option<String>,
+
+	pub indexer_repository_id: String,
+	pub indexer_update_on_events: bool,
+
+	pub users_file: Option<String>,
+	pub users_object: String,
+
+	pub oidc_file: Option<String>,
+	pub oidc_object: String,
+
+	pub auth_mode: AuthMode,
+	pub gateway_name_claim: String,
+	pub gateway_rules_file: Option<String>,
+	pub gateway_rules_object: String,
+
+	pub login_max_failures: u32,
+	pub login_lockout_seconds: i64,
+	pub login_failures_shared: bool,
+
+	pub username: Option<String>,
+	pub password: Option<String>,
+
+	// the users, oidc and gateway rules, from their table in the config file or their file
+	pub sections: Sections
+}
+
+// Each one is the same document the separate file would hold
+#[derive(Default)]
+pub struct Sections {
+	pub users: Option<Value>,
+	pub oidc: Option<Value>,
+	pub gateway: Option<Value>
+}
 
-	pub username: String,
-	pub password: String
+// Everything wrong with the config, so it can all be fixed in one go instead of one deploy per typo
+#[derive(Debug)]
+pub struct ConfigError {
+	pub problems: Vec<String>
+}
+
+impl Display for ConfigError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self.problems.len() {
+			1 => { write!(f, "The configuration has a problem:")? }
+			count => { write!(f, "The configuration has {count} problems:")? }
+		}
+		for problem in &self.problems {
+			write!(f, "\n - {problem}")?;
+		}
+		Ok(())
+	}
+}
+
+impl std::error::Error for ConfigError {}
+
+// Reading anything under these needs credentials, the same as uploading
+#[derive(Clone)]
+pub struct PrivatePaths {
+	pub everything: bool,
+	pub prefixes: Vec<String>
+}
+
+impl PrivatePaths {
+	// "com/secret" is the directory itself, so it counts as under "com/secret/" too
+	pub fn is_private(&self, path: &str) -> bool {
+		let directory = format!("{}/", path.trim_end_matches('/'));
+		self.everything || self.prefixes.iter().any(|it| { directory.starts_with(it) })
+	}
+
+	// Whether anything private is at or below a path, a listing of "com/" shows "com/secret/" to whoever can read it
+	pub fn reaches(&self, path: &str) -> bool {
+		let directory = if path.is_empty() { String::new() } else { format!("{}/", path.trim_end_matches('/')) };
+		self.is_private(path) || self.prefixes.iter().any(|it| { it.starts_with(&directory) })
+	}
+}
+
+// Where callers prove who they are, us checking the Authorization header or an authorizer in api gateway
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+	Function,
+	Gateway,
+	// the gateway's identity when it has one, so routes without an authorizer can still use credentials
+	Either
+}
+
+// What the index pages look like, empty variables turn the optional ones off
+#[derive(Serialize)]
+pub struct Branding {
+	pub title: String,
+	pub description: String,
+	pub icon: Option<String>,
+	pub image: Option<String>,
+	pub theme_color: Option<String>,
+	pub accent_color: Option<String>,
+	pub footer: Option<String>
 }
 
 impl MavenConfig {
-	pub fn new() -> MavenConfig {
-		MavenConfig {
-			bucket_name: std::env::var("BUCKET_NAME")
-				.expect("A BUCKET_NAME must be set in this app's Lambda environment variables."),
-
-			indexing_enabled: std::env::var("INDEXING_ENABLED")
-				.unwrap_or_else(|_| { String::from("true") })
-				.parse().expect("Failed to read boolean from environment variable INDEXING_ENABLED."),
-			indexing_max_keys: std::env::var("INDEXING_MAX_KEYS")
-				.unwrap_or_else(|_| { String::from("1000") })
-				.parse().expect("Failed to read i32 from environment variable INDEXING_MAX_KEYS."),
+	// Anything outside the maven layout is refused on upload, unless it's under one of the raw prefixes
+	pub fn allows_upload_to(&self, key: &str) -> bool {
+		layout::parse(key).is_some() || self.raw_path_prefixes.iter().any(|it| { key.starts_with(it) })
+	}
+
+	// Built once when an instance starts. Settings come from the environment, then the optional config file
+	// (CONFIG_FILE shipped with the lambda, or CONFIG_OBJECT in the bucket), then the defaults
+	pub async fn load(sdk_config: &SdkConfig, s3_client: &Client) -> Result<MavenConfig, ConfigError> {
+		let mut settings = Settings::new();
+		let bucket_name = settings.required("BUCKET_NAME", "A BUCKET_NAME must be set in this app's Lambda environment variables.");
+		let mut sections = settings.read_file(s3_client, &bucket_name).await;
+		settings.resolve_secrets(sdk_config).await;
+
+		let config = MavenConfig {
+			bucket_name: bucket_name.clone(),
+
+			indexing_enabled: settings.parse("INDEXING_ENABLED", true),
+			indexing_max_keys: settings.parse("INDEXING_MAX_KEYS", 1000),
 
 			// We can only serve files <6MB in size from a lambda without streaming (not implemented)
 			// so avoid allowing uploading anything that violates the size restriction
-			max_artifact_size: std::env::var("MAX_ARTIFACT_SIZE")
-				.unwrap_or_else(|_| { String::from("5900000") })
-				.parse().expect("Failed to read i64 from environment variable MAX_ARTIFACT_SIZE."),
+			max_artifact_size: settings.parse("MAX_ARTIFACT_SIZE", 5900000),
+
+			// Uploads have to follow the maven layout, except under these comma separated prefixes
+			raw_path_prefixes: settings.prefix_list("RAW_PATH_PREFIXES"),
+
+			// Reads are anonymous unless the whole repository or the prefix they are under is private
+			private_paths: PrivatePaths {
+				everything: settings.parse("PRIVATE_REPOSITORY", false),
+				prefixes: settings.prefix_list("PRIVATE_PREFIXES")
+			},
+
+			branding: Branding {
+				title: settings.string("BRAND_TITLE", "Silver's Silly Little Maven"),
+				description: settings.string("BRAND_DESCRIPTION", "A maven repository"),
+				icon: settings.optional("BRAND_ICON", Some("https://silverandro.dev/favicon.ico")),
+				image: settings.optional("BRAND_IMAGE", Some("https://www.silverandro.dev/site_image.png")),
+				theme_color: settings.optional("BRAND_THEME_COLOR", Some("#B00B69")),
+				accent_color: settings.optional("BRAND_ACCENT_COLOR", None),
+				footer: settings.optional("BRAND_FOOTER", None)
+			},
+
+			// Where users reach the repository, shown in the usage snippets. Worked out from each request when unset,
+			// which is right unless something in front of api gateway rewrites the host or path
+			public_url: settings.optional("PUBLIC_URL", None)
+				.map(|it| { format!("{}/", it.trim_end_matches('/')) }),
+
+			// Identifies the repository in the maven indexer export, IDEs show it next to search results
+			indexer_repository_id: settings.string("INDEXER_REPOSITORY_ID", &bucket_name),
+			// Rebuilding the export on every s3 event is a full pass over the index, so its opt in
+			indexer_update_on_events: settings.parse("INDEXER_UPDATE_ON_EVENTS", false),
+
+			// Users with hashed passwords, from a file shipped with the lambda or an object in the bucket
+			users_file: settings.optional("USERS_FILE", None),
+			users_object: settings.string("USERS_OBJECT", ".maven/users.json"),
+
+			// Issuers whose JWTs can publish, like github actions, found the same way as the users
+			oidc_file: settings.optional("OIDC_FILE", None),
+			oidc_object: settings.string("OIDC_OBJECT", ".maven/oidc.json"),
+
+			// "gateway" trusts the authorizer and mtls info api gateway attaches to the request, and ignores the header
+			auth_mode: match settings.string("AUTH_MODE", "function").as_str() {
+				"function" => { AuthMode::Function }
+				"gateway" => { AuthMode::Gateway }
+				"either" => { AuthMode::Either }
+				other => {
+					settings.problem(format!("Unknown AUTH_MODE \"{other}\", expected function, gateway or either."));
+					AuthMode::Function
+				}
+			},
+			// The claim that names the caller in logs, certificates without it go by their subject
+			gateway_name_claim: settings.string("GATEWAY_NAME_CLAIM", "sub"),
+			// What callers the gateway vouches for can do, found the same way as the users
+			gateway_rules_file: settings.optional("GATEWAY_RULES_FILE", None),
+			gateway_rules_object: settings.string("GATEWAY_RULES_OBJECT", ".maven/gateway.json"),
+
+			// Wrong credentials this many times within the lockout gets a username or ip a 429 until it's over, 0 turns it off
+			login_max_failures: settings.parse("LOGIN_MAX_FAILURES", 10),
+			login_lockout_seconds: settings.parse("LOGIN_LOCKOUT_SECONDS", 900),
+			// Counts in the bucket instead of per instance, an attacker hitting many instances at once can't spread
+			// their guesses out then, at the cost of a few more requests to s3 per login
+			login_failures_shared: settings.parse("LOGIN_FAILURES_SHARED", false),
+
+			// The original single upload user, still works next to the users file
+			// Point these at AWS Secrets or the parameter store with "secret://name#key" or "ssm://name" rather than
+			// putting the password itself in the environment
+			username: settings.optional("UPLOAD_USERNAME", None),
+			password: settings.optional("UPLOAD_PASSWORD", None),
+
+			sections: Sections::default()
+		};
+
+		// the separate files are read now too, so one that can't be read is just another problem in the list
+		settings.read_section_file("USERS_FILE", config.users_file.as_deref(), &mut sections.users);
+		settings.read_section_file("OIDC_FILE", config.oidc_file.as_deref(), &mut sections.oidc);
+		settings.read_section_file("GATEWAY_RULES_FILE", config.gateway_rules_file.as_deref(), &mut sections.gateway);
+		let config = MavenConfig { sections, ..config };
+
+		config.validate(&mut settings);
+		settings.check_unused();
+		if settings.problems.is_empty() {
+			Ok(config)
+		} else {
+			Err(ConfigError { problems: settings.problems })
+		}
+	}
+
+	// What can't be caught just by reading each value on its own
+	fn validate(&self, settings: &mut Settings) {
+		if !(1..=1000).contains(&self.indexing_max_keys) {
+			settings.problem(String::from("INDEXING_MAX_KEYS has to be between 1 and 1000, s3 doesn't list more at once."));
+		}
+		if self.max_artifact_size <= 0 {
+			settings.problem(String::from("MAX_ARTIFACT_SIZE has to be more than 0."));
+		}
+		if self.login_lockout_seconds <= 0 {
+			settings.problem(String::from("LOGIN_LOCKOUT_SECONDS has to be more than 0."));
+		}
+		if let Some(url) = &self.public_url {
+			if !url.starts_with("https://") && !url.starts_with("http://") {
+				settings.problem(format!("PUBLIC_URL \"{url}\" has to start with https:// or http://."));
+			}
+		}
+		if self.username.is_some() != self.password.is_some() {
+			settings.problem(String::from("UPLOAD_USERNAME and UPLOAD_PASSWORD only work together, set both or neither."));
+		}
+	}
+}
+
+// The sections of the config file that aren't plain settings
+const SECTIONS: [&str; 3] = ["users", "oidc", "gateway"];
+
+// Reads settings by their environment variable name, and remembers what was wrong instead of panicking
+struct Settings {
+	// plain values from the config file, keyed by the environment variable they stand in for
+	file: HashMap<String, String>,
+	used: HashSet<String>,
+	problems: Vec<String>
+}
 
-			// Realistically these should be in AWS Secrets and not env variables, but theres no SDK for
-			// AWS Secrets yet, and i dont have strict security requirements
-			username: std::env::var("UPLOAD_USERNAME")
-				.expect("Failed to get username from UPLOAD_USERNAME for uploading artifacts."),
-			password: std::env::var("UPLOAD_PASSWORD")
-				.expect("Failed to get password from UPLOAD_PASSWORD for uploading artifacts.")
+impl Settings {
+	fn new() -> Settings {
+		Settings {
+			file: HashMap::new(),
+			used: HashSet::new(),
+			problems: Vec::new()
 		}
 	}
+
+	fn problem(&mut self, problem: String) {
+		self.problems.push(problem);
+	}
+
+	// TOML unless the name ends in .json
+	// "private_prefixes = ["com/example/internal/"]" sets PRIVATE_PREFIXES, lists are the same as comma separated values
+	async fn read_file(&mut self, s3_client: &Client, bucket_name: &str) -> Sections {
+		let (name, source) = match std::env::var("CONFIG_FILE") {
+			Ok(path) => {
+				match std::fs::read_to_string(&path) {
+					Ok(source) => { (path, source) }
+					Err(err) => {
+						self.problem(format!("Failed to read CONFIG_FILE \"{path}\". {err}"));
+						return Sections::default()
+					}
+				}
+			}
+			Err(_) => {
+				let key = std::env::var("CONFIG_OBJECT").unwrap_or_else(|_| { String::from(".maven/config.toml") });
+				match storage::get_object_text(s3_client, bucket_name, &key).await {
+					None => { return Sections::default() }
+					Some(source) => { (key, source) }
+				}
+			}
+		};
+
+		let document: Result<Value, String> = if name.ends_with(".json") {
+			serde_json::from_str(&source).map_err(|err| { err.to_string() })
+		} else {
+			toml::from_str(&source).map_err(|err| { err.to_string() })
+		};
+		let document = match document {
+			Ok(Value::Object(document)) => { document }
+			Ok(_) => {
+				self.problem(format!("\"{name}\" has to be a table of settings."));
+				return Sections::default()
+			}
+			Err(err) => {
+				self.problem(format!("\"{name}\" is invalid. {err}"));
+				return Sections::default()
+			}
+		};
+
+		let mut sections = Sections::default();
+		for (key, value) in document {
+			match (key.as_str(), value) {
+				// the users file has the users under a "users" key of its own
+				("users", value) => { sections.users = Some(serde_json::json!({ "users": value })) }
+				("oidc", value) => { sections.oidc = Some(value) }
+				("gateway", value) => { sections.gateway = Some(value) }
+				(_, Value::Array(items)) => {
+					let items: Option<Vec<String>> = items.into_iter().map(scalar_text).collect();
+					match items {
+						None => { self.problem(format!("\"{key}\" in \"{name}\" can only list plain values.")) }
+						Some(items) => { self.file.insert(key.to_uppercase(), items.join(",")); }
+					}
+				}
+				(_, value) => {
+					match scalar_text(value) {
+						None => { self.problem(format!("\"{key}\" in \"{name}\" has to be a plain value, only {} can be tables.", SECTIONS.join(", "))) }
+						Some(value) => { self.file.insert(key.to_uppercase(), value); }
+					}
+				}
+			}
+		}
+		sections
+	}
+
+	// A users, oidc or gateway rules file shipped with the lambda, unless the config file already has that section
+	fn read_section_file(&mut self, name: &str, file: Option<&str>, section: &mut Option<Value>) {
+		let file = match file {
+			Some(file) if section.is_none() => { file }
+			_ => { return }
+		};
+
+		let source = match std::fs::read_to_string(file) {
+			Err(err) => {
+				self.problem(format!("{name} \"{file}\" can't be read. {err}"));
+				return
+			}
+			Ok(source) => { source }
+		};
+		match serde_json::from_str(&source) {
+			Err(err) => { self.problem(format!("{name} \"{file}\" is not valid json. {err}")) }
+			Ok(value) => { *section = Some(value) }
+		}
+	}
+
+	// Fetches whatever secret:// and ssm:// references the settings hold, before anything reads them
+	async fn resolve_secrets(&mut self, sdk_config: &SdkConfig) {
+		let references = std::env::vars().map(|(_, value)| { value })
+			.chain(self.file.values().cloned())
+			.filter(|it| { secrets::is_reference(it) });
+		secrets::load(sdk_config, references).await;
+	}
+
+	// None when unset, from the environment first and the config file second
+	fn var(&mut self, name: &str) -> Option<String> {
+		self.used.insert(String::from(name));
+		let value = std::env::var(name).ok().or_else(|| { self.file.get(name).cloned() })?;
+		match secrets::resolve(&value) {
+			Ok(value) => { Some(value) }
+			Err(err) => {
+				self.problem(format!("Failed to resolve {name}. {err}"));
+				None
+			}
+		}
+	}
+
+	fn required(&mut self, name: &str, message: &str) -> String {
+		match self.var(name) {
+			Some(value) if !value.is_empty() => { value }
+			_ => {
+				self.problem(String::from(message));
+				String::new()
+			}
+		}
+	}
+
+	fn string(&mut self, name: &str, default: &str) -> String {
+		self.var(name).unwrap_or_else(|| { String::from(default) })
+	}
+
+	// Unset falls back to the default, set but empty means "none"
+	fn optional(&mut self, name: &str, default: Option<&str>) -> Option<String> {
+		match self.var(name) {
+			None => { default.map(String::from) }
+			Some(value) if value.is_empty() => { None }
+			Some(value) => { Some(value) }
+		}
+	}
+
+	fn parse<T: FromStr>(&mut self, name: &str, default: T) -> T {
+		match self.var(name) {
+			None => { default }
+			Some(value) => {
+				value.trim().parse().unwrap_or_else(|_| {
+					let kind = std::any::type_name::<T>();
+					self.problem(format!("Failed to read {kind} from {name}, \"{}\" isn't one.", value.escape_debug()));
+					default
+				})
+			}
+		}
+	}
+
+	// "a, b/" -> ["a/", "b/"]
+	fn prefix_list(&mut self, name: &str) -> Vec<String> {
+		self.optional(name, None)
+			.map(|it| {
+				it.split(',')
+					.map(|prefix| { prefix.trim().trim_matches('/') })
+					.filter(|prefix| { !prefix.is_empty() })
+					.map(|prefix| { format!("{prefix}/") })
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+
+	// A setting in the file nothing reads is most likely a typo
+	fn check_unused(&mut self) {
+		let mut unused: Vec<String> = self.file.keys().filter(|it| { !self.used.contains(*it) }).cloned().collect();
+		unused.sort();
+		for key in unused {
+			self.problem(format!("Unknown setting \"{}\" in the config file.", key.to_lowercase()));
+		}
+	}
+}
+
+fn scalar_text(value: Value) -> Option<String> {
+	match value {
+		Value::String(text) => { Some(text) }
+		Value::Bool(_) | Value::Number(_) => { Some(value.to_string()) }
+		_ => { None }
+	}
 }
@@ -0,0 +1,107 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use aws_lambda_events::s3::{S3Event, S3EventRecord};
+use aws_sdk_s3::Client;
+use lambda_runtime::Error;
+use percent_encoding::percent_decode_str;
+use crate::cfg::MavenConfig;
+use crate::maven::layout;
+use crate::maven::layout::MavenPath;
+use crate::storage;
+use crate::storage::{checksums, metadata, nexus_index, repository_index};
+use crate::storage::layers::Layer;
+
+// Keeps checksums, metadata and the persisted index in sync with the bucket
+// Needed for anything that puts objects in the bucket without going through us, like `aws s3 sync`
+pub async fn handle_s3_event(s3_client: &Client, maven_config: &MavenConfig, bucket_index: &Arc<Mutex<Layer>>, event: S3Event) -> Result<(), Error> {
+	let mut artifact_directories: BTreeSet<String> = BTreeSet::new();
+	let mut changed_directories: BTreeSet<String> = BTreeSet::new();
+	let mut metadata_keys: BTreeSet<String> = BTreeSet::new();
+
+	for record in event.records {
+		let key = match object_key(&record, maven_config) {
+			None => { continue }
+			Some(key) => { key }
+		};
+
+		// Anything we generate ourselves also fires events, skipping them is what stops us from looping forever
+		if storage::is_internal(&key) || nexus_index::is_index_file(&key) || (checksums::is_checksum(&key) && !metadata::is_metadata(&key)) {
+			continue
+		}
+
+		// Metadata and its checksums come from us and from deploying clients, in whatever order their writes land
+		// Whichever was last, the checksums are checked against the metadata that is there now once the batch is done,
+		// and only ones that don't match are rewritten, so the events that fires settle down once everything agrees
+		let event_name = record.event_name.unwrap_or_default();
+		if metadata::is_metadata(&key) {
+			if event_name.starts_with("ObjectCreated") {
+				let metadata_key = if checksums::is_checksum(&key) { key.rsplit_once('.').map(|it| { it.0 }).unwrap_or(&key) } else { &key };
+				metadata_keys.insert(String::from(metadata_key));
+			}
+			continue
+		}
+
+		tracing::info!("Handling {event_name} for \"{key}\"");
+
+		// one bad key shouldn't hold up the rest of the batch, or have lambda retry all of it
+		let result = if event_name.starts_with("ObjectCreated") {
+			checksums::regenerate(s3_client, maven_config, &key).await
+		} else if event_name.starts_with("ObjectRemoved") {
+			checksums::remove(s3_client, maven_config, &key).await
+		} else {
+			continue
+		};
+		if let Err(err) = result {
+			tracing::error!("Failed to update the checksums of \"{key}\". {err}");
+		}
+
+		// the object changed either way, so the index still has to catch up with it
+		changed_directories.insert(key.rsplit_once('/').map(|it| { String::from(it.0) }).unwrap_or_default());
+		if let Some(MavenPath::Artifact(artifact)) = layout::parse(&key) {
+			artifact_directories.insert(artifact.artifact_directory());
+		}
+	}
+
+	for directory in artifact_directories {
+		if let Err(err) = metadata::regenerate(s3_client, maven_config, &directory).await {
+			tracing::error!("Failed to regenerate the metadata of \"{directory}\". {err}");
+		}
+	}
+
+	for key in metadata_keys {
+		if let Err(err) = checksums::verify(s3_client, maven_config, &key).await {
+			tracing::error!("Failed to check the checksums of \"{key}\". {err}");
+		}
+	}
+
+	if !changed_directories.is_empty() {
+		let index = repository_index::update(s3_client, maven_config, &changed_directories).await?;
+		if maven_config.indexer_update_on_events {
+			nexus_index::rebuild(s3_client, maven_config, &index).await?;
+		}
+
+		// Only clears the cache of this instance, others still catch up once they get recycled
+		let mut root_layer = bucket_index.lock().unwrap();
+		*root_layer = Layer::new();
+	}
+
+	Ok(())
+}
+
+// Keys in s3 events are url encoded, with spaces as '+'
+fn object_key(record: &S3EventRecord, maven_config: &MavenConfig) -> Option<String> {
+	let bucket = record.s3.bucket.name.as_deref().unwrap_or_default();
+	if bucket != maven_config.bucket_name {
+		tracing::warn!("Ignoring event for bucket \"{bucket}\", we only serve \"{}\"", maven_config.bucket_name);
+		return None
+	}
+
+	let raw_key = record.s3.object.key.as_deref()?.replace('+', " ");
+	match percent_decode_str(&raw_key).decode_utf8() {
+		Ok(key) => { Some(key.into_owned()) }
+		Err(err) => {
+			tracing::warn!("Ignoring event for undecodable key \"{raw_key}\". {err}");
+			None
+		}
+	}
+}
@@ -0,0 +1,91 @@
+use aws_sdk_s3::Client;
+use lambda_http::Request;
+use crate::cfg::MavenConfig;
+use crate::maven::version::MavenVersion;
+use crate::storage;
+use crate::storage::metadata;
+use crate::util;
+use crate::util::query_param;
+
+// What /api/latest was asked for, the classifier and extension only matter when redirecting to a file
+pub struct LatestQuery {
+	pub group_id: String,
+	pub artifact_id: String,
+	// "com/example/lib" for g=com.example&a=lib
+	pub artifact_directory: String,
+	pub snapshots: bool,
+	pub redirect: bool,
+	pub classifier: Option<String>,
+	pub extension: String
+}
+
+impl LatestQuery {
+	// None if the group or artifact is missing, or they don't make up a directory anyone could read
+	pub fn from_request(request: &Request) -> Option<LatestQuery> {
+		let flag = |name: &str| { query_param(request, name).is_some_and(|it| { it == "true" }) };
+		let group_id = query_param(request, "g")?;
+		let artifact_id = query_param(request, "a")?;
+		let artifact_directory = util::artifact_directory(&format!("{}/{artifact_id}", group_id.replace('.', "/")))?;
+
+		Some(LatestQuery {
+			group_id,
+			artifact_id,
+			artifact_directory,
+			snapshots: flag("snapshots"),
+			redirect: flag("redirect"),
+			classifier: query_param(request, "c"),
+			extension: query_param(request, "e").unwrap_or(String::from("jar"))
+		})
+	}
+}
+
+// Finds the newest version of an artifact by maven ordering, from maven-metadata.xml if there is one
+// and from the bucket listing otherwise
+pub async fn resolve_version(s3_client: &Client, maven_config: &MavenConfig, artifact_directory: &str, snapshots: bool) -> Option<String> {
+	let metadata_key = format!("{artifact_directory}/{}", metadata::METADATA_FILE);
+	let from_metadata = match storage::get_resource(s3_client, maven_config, &metadata_key).await {
+		None => { None }
+		Some(obj) => {
+			let bytes = obj.body.collect().await.ok()?.into_bytes();
+			metadata::parse_versions(&String::from_utf8_lossy(&bytes))
+		}
+	};
+
+	let versions = match from_metadata {
+		Some(versions) if !versions.is_empty() => { versions }
+		_ => {
+			metadata::list_versions(s3_client, maven_config, artifact_directory).await.ok()?
+				.into_keys()
+				.collect()
+		}
+	};
+
+	versions.into_iter()
+		.map(|it| { MavenVersion::parse(&it) })
+		.filter(|it| { snapshots || !it.is_snapshot() })
+		.max()
+		.map(|it| { it.to_string() })
+}
+
+// Works out the key of a specific file of a version, snapshots get looked up in their own metadata
+// since they are usually deployed with a timestamp instead of "SNAPSHOT" in the file name
+pub async fn resolve_file(s3_client: &Client, maven_config: &MavenConfig, query: &LatestQuery, version: &str) -> String {
+	let version_directory = format!("{}/{version}", query.artifact_directory);
+	let classifier = query.classifier.as_deref();
+
+	let mut file_version = String::from(version);
+	if version.ends_with("-SNAPSHOT") {
+		let metadata_key = format!("{version_directory}/{}", metadata::METADATA_FILE);
+		if let Some(obj) = storage::get_resource(s3_client, maven_config, &metadata_key).await {
+			if let Ok(bytes) = obj.body.collect().await {
+				let xml = String::from_utf8_lossy(&bytes.into_bytes()).into_owned();
+				if let Some(value) = metadata::parse_snapshot_value(&xml, classifier, &query.extension) {
+					file_version = value;
+				}
+			}
+		}
+	}
+
+	let classifier_suffix = classifier.map(|it| { format!("-{it}") }).unwrap_or_default();
+	format!("{version_directory}/{}-{file_version}{classifier_suffix}.{}", query.artifact_id, query.extension)
+}
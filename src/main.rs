@@ -1,19 +1,36 @@
 mod storage;
+mod auth;
+mod badge;
 mod cfg;
+mod events;
+mod latest;
+mod maven;
 mod responses;
+mod search;
+mod secrets;
 mod util;
 
 use std::sync::{Arc, Mutex};
-use http::{Method, Response};
+use http::{HeaderValue, Method, Response};
+use aws_lambda_events::s3::S3Event;
 use aws_sdk_s3::Client;
-use data_encoding::BASE64;
-use lambda_http::request::RequestContext;
+use lambda_http::request::{LambdaRequest, RequestContext};
 use lambda_http::{Body, Request, RequestExt};
-use lambda_runtime::{service_fn, Error};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use serde_json::Value;
 use crate::responses::build_response::{ResponseBuilder, ErrorResponseBuilder};
-use crate::cfg::MavenConfig;
+use crate::responses::Templates;
+use crate::auth::{AuthFailure, Authenticator, Identity, ReadAccess};
+use crate::auth::rules::Action;
+use crate::cfg::{AuthMode, MavenConfig};
+use crate::badge::BadgeQuery;
+use crate::latest::LatestQuery;
+use crate::search::SearchQuery;
+use crate::storage::audit;
+use crate::storage::audit::AuditQuery;
 use crate::storage::layers::Layer;
-use crate::util::is_file_request;
+use crate::maven::layout;
+use crate::util::{base_url, canonical_path, index_sort, into_gateway_response, wants_json};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -26,19 +43,65 @@ async fn main() -> Result<(), Error> {
     let config = aws_config::load_from_env().await;
     let s3_client = Client::new(&config);
     let root_layer: Arc<Mutex<Layer>> = Arc::new(Mutex::new(Layer::new()));
+    // a broken config fails the instance on start, with everything wrong with it in one error
+    let maven_config = match MavenConfig::load(&config, &s3_client).await {
+        Ok(maven_config) => { maven_config }
+        Err(err) => {
+            tracing::error!("{err}");
+            return Err(err.into())
+        }
+    };
+    let templates = Templates::load(&s3_client, &maven_config).await;
+    let authenticator = match Authenticator::load(&s3_client, &maven_config).await {
+        Ok(authenticator) => { authenticator }
+        Err(err) => {
+            tracing::error!("{err}");
+            return Err(err.into())
+        }
+    };
     
     // need to curry together a proper invocation
     // result of what i understand is a strange restriction in the SDK about what
     // specific types of captures can be passed to the lambda service
-    lambda_http::run(service_fn(|event| {
-        handler(event, MavenConfig::new(), &s3_client, &root_layer)
+    lambda_runtime::run(service_fn(|event: LambdaEvent<Value>| {
+        dispatch(event, &maven_config, &s3_client, &templates, &authenticator, &root_layer)
     })).await
 }
 
+// The same function is invoked by the gateway and by s3 event notifications, so we have to
+// tell them apart ourselves instead of letting lambda_http::run assume everything is http
+async fn dispatch(
+    event: LambdaEvent<Value>,
+    maven_config: &MavenConfig,
+    s3_client: &Client,
+    templates: &Templates,
+    authenticator: &Authenticator,
+    bucket_index: &Arc<Mutex<Layer>>
+) -> Result<Value, Error> {
+    let (payload, context) = event.into_parts();
+
+    if is_s3_event(&payload) {
+        let s3_event: S3Event = serde_json::from_value(payload)?;
+        events::handle_s3_event(s3_client, maven_config, bucket_index, s3_event).await?;
+        return Ok(Value::Null)
+    }
+
+    let lambda_request: LambdaRequest = serde_json::from_value(payload)?;
+    let request = Request::from(lambda_request).with_lambda_context(context);
+    let response = handler(request, maven_config, s3_client, templates, authenticator, bucket_index).await?;
+    Ok(serde_json::to_value(into_gateway_response(response))?)
+}
+
+fn is_s3_event(payload: &Value) -> bool {
+    payload["Records"][0]["eventSource"] == "aws:s3"
+}
+
 async fn handler(
     event: Request,
-    maven_config: MavenConfig,
+    maven_config: &MavenConfig,
     s3_client: &Client,
+    templates: &Templates,
+    authenticator: &Authenticator,
     bucket_index: &Arc<Mutex<Layer>>
 ) -> Result<Response<Body>, Error> {
     let raw_context = event.request_context();
@@ -53,99 +116,301 @@ async fn handler(
             let http_method = context.http.method;
 
             // get a simple string we can work with
-            let mut request_path = String::from(event.raw_http_path());
+            let mut raw_path = String::from(event.raw_http_path());
             // remove first slash, always present as far as I can tell
-            request_path.remove(0);
+            raw_path.remove(0);
             // remove stage prefix
-            request_path = match context.stage {
-                None => { request_path }
-                Some(stage) => { String::from(request_path.trim_start_matches(format!("{stage}/").as_str())) }
+            raw_path = match context.stage {
+                None => { raw_path }
+                Some(stage) => { String::from(raw_path.strip_prefix(format!("{stage}/").as_str()).unwrap_or(&raw_path)) }
+            };
+
+            // everything after this works on the decoded path, which ends up as an s3 key as is
+            let request_path = match canonical_path(&raw_path) {
+                None => {
+                    tracing::warn!("Refusing a request for the invalid path \"{}\"", raw_path.escape_debug());
+                    return ErrorResponseBuilder::invalid_path()
+                }
+                Some(path) => { path }
             };
 
             // check if this is an index request
-            let is_indexing_request = http_method == Method::GET && !is_file_request(&request_path);
+            let is_indexing_request = http_method == Method::GET && !layout::is_file_path(&request_path);
 
             tracing::info!("Handling a request for \"{request_path}\" with method {http_method}. Indexing: {is_indexing_request}");
 
+            // our own bookkeeping is never exposed
+            if storage::is_internal(&request_path) {
+                return ErrorResponseBuilder::no_content()
+            }
+
+            // managing api tokens, only ever with the password of the user they belong to so a leaked token can't mint more
+            if request_path == "api/tokens" || request_path.starts_with("api/tokens/") {
+                if maven_config.auth_mode == AuthMode::Gateway {
+                    return ErrorResponseBuilder::no_tokens_allowed()
+                }
+                let owner = match authenticator.authenticate_owner(&event, s3_client, maven_config).await {
+                    Err(failure) => { return deny_auth(failure) }
+                    Ok(owner) => { owner.name }
+                };
+
+                return match request_path.strip_prefix("api/tokens/") {
+                    None if http_method == Method::GET => { ResponseBuilder::tokens(s3_client, maven_config, &owner).await }
+                    None if http_method == Method::POST => { ResponseBuilder::create_token(s3_client, maven_config, &owner, event.body()).await }
+                    Some(id) if http_method == Method::DELETE => { ResponseBuilder::revoke_token(s3_client, maven_config, &owner, id).await }
+                    _ => { ErrorResponseBuilder::invalid_request_method(http_method) }
+                }
+            }
+
+            // reading the audit log, only for admins allowed to delete where it's kept
+            // a read grant isn't enough, "**" hands that to every reader of the repository
+            if http_method == Method::GET && request_path == "api/audit" {
+                if let Err(denied) = check_authorization(&event, s3_client, maven_config, authenticator, Action::Delete, audit::AUDIT_PREFIX).await { return denied.response }
+                return match AuditQuery::from_request(&event) {
+                    None => { ErrorResponseBuilder::invalid_request() }
+                    Some(query) => { ResponseBuilder::audit(s3_client, maven_config, &query).await }
+                }
+            }
+
+            let is_read = http_method == Method::GET || http_method == Method::HEAD;
+
+            // resolving the latest version is a lookup of a single artifact, not a listing, so it works without indexing
+            if http_method == Method::GET && request_path == "api/latest" {
+                let query = match LatestQuery::from_request(&event) {
+                    None => { return ErrorResponseBuilder::invalid_request() }
+                    Some(query) => { query }
+                };
+                let read_access = match read_access(&event, s3_client, maven_config, authenticator, Some(&query.artifact_directory)).await {
+                    Err(response) => { return response }
+                    Ok(read_access) => { read_access }
+                };
+                if !read_access.can_read(&query.artifact_directory) {
+                    return deny_read(&read_access)
+                }
+                let personal = read_access.is_personal(&query.artifact_directory);
+                return finish_read(personal, ResponseBuilder::latest(s3_client, maven_config, &query).await)
+            }
+
+            // badges resolve the same way, just rendered for a readme
+            // anything under "badge/" that isn't one is left to the normal handling, a group can be called that too
+            let badge = if http_method == Method::GET { BadgeQuery::from_request(&event, &request_path) } else { None };
+            if let Some(query) = badge {
+                let read_access = match read_access(&event, s3_client, maven_config, authenticator, Some(&query.artifact_directory)).await {
+                    Err(response) => { return response }
+                    Ok(read_access) => { read_access }
+                };
+                if !read_access.can_read(&query.artifact_directory) {
+                    return deny_read(&read_access)
+                }
+                let personal = read_access.is_personal(&query.artifact_directory);
+                return finish_read(personal, ResponseBuilder::badge(s3_client, maven_config, &query).await)
+            }
+
             // return an error if we dont allow indexing
             if is_indexing_request && !maven_config.indexing_enabled {
                 return ErrorResponseBuilder::no_index_allowed()
             }
 
+            // searching goes over the same listing as indexing, so it follows the same rule
+            if is_indexing_request && (request_path == "search" || request_path == "api/search") {
+                let read_access = match read_access(&event, s3_client, maven_config, authenticator, None).await {
+                    Err(response) => { return response }
+                    Ok(read_access) => { read_access }
+                };
+                let as_json = request_path == "api/search" || wants_json(&event);
+                let personal = read_access.identity.is_some();
+                return finish_read(personal, ResponseBuilder::search(s3_client, maven_config, templates, &read_access, &SearchQuery::from_request(&event), as_json).await)
+            }
+
+            // everything left that reads works on the request path itself
+            let read_access = if is_read {
+                match read_access(&event, s3_client, maven_config, authenticator, Some(&request_path)).await {
+                    Err(response) => { return response }
+                    Ok(read_access) => { read_access }
+                }
+            } else {
+                ReadAccess { private_paths: maven_config.private_paths.clone(), identity: None }
+            };
+            if is_read && !read_access.can_read(&request_path) {
+                return deny_read(&read_access)
+            }
+            let personal = read_access.is_personal(&request_path);
+
+            // relative links on index pages only work from "com/example/", so send "com/example" there first
+            if is_indexing_request && !request_path.is_empty() && !request_path.ends_with('/') {
+                return finish_read(personal, ResponseBuilder::directory_redirect(s3_client, maven_config, bucket_index, &request_path, event.uri().query()).await)
+            }
+
             // build and return an index
             if is_indexing_request {
-                return ResponseBuilder::index(s3_client, maven_config, bucket_index, &request_path).await
+                let base_url = base_url(&event, maven_config, &raw_path);
+                return finish_read(personal, ResponseBuilder::index(s3_client, maven_config, templates, bucket_index, &read_access, &request_path, &base_url, wants_json(&event), index_sort(&event)).await)
             }
 
             // just generate the headers for the request
             // cloudflare converts these to GET requests but
             // no reason we cant add support here
             if http_method == Method::HEAD {
-                return ResponseBuilder::resource_head(s3_client, maven_config, &request_path).await
+                return finish_read(personal, ResponseBuilder::resource_head(s3_client, maven_config, &request_path).await)
             }
 
             // requesting an artifact
             if http_method == Method::GET {
-                return ResponseBuilder::resource(s3_client, maven_config, &request_path).await
+                let response = ResponseBuilder::resource(s3_client, maven_config, &request_path).await?;
+                // "com/example/lib.core" only looks like a file, a dotted artifact directory gets the same redirect as any other
+                if response.status() == 404 && maven_config.indexing_enabled && layout::parse(&request_path).is_none() {
+                    return finish_read(personal, ResponseBuilder::directory_redirect(s3_client, maven_config, bucket_index, &request_path, event.uri().query()).await)
+                }
+                return finish_read(personal, Ok(response))
+            }
+
+            // rebuilding the maven indexer export on demand, for buckets that dont rebuild it on events
+            if http_method == Method::POST && request_path == "api/index/rebuild" {
+                // the index covers everything, so rebuilding it needs write access to all of it
+                if let Err(denied) = check_authorization(&event, s3_client, maven_config, authenticator, Action::Write, storage::repository_index::INDEX_KEY).await { return denied.response }
+
+                let index = storage::repository_index::rebuild(s3_client, maven_config).await?;
+                storage::nexus_index::rebuild(s3_client, maven_config, &index).await?;
+                return ResponseBuilder::rebuilt_index()
             }
 
-            // uploading an artifact
+            // uploading an artifact, every attempt with valid credentials ends up in the audit log whether it went through or not
+            // ones without them, or throttled ones, only go to the function's log
             if http_method == Method::PUT {
-                if request_path.is_empty() { return ErrorResponseBuilder::invalid_request() }
-
-                // verify the authorization
-                let auth_header = event.headers().get("Authorization");
-                return match auth_header {
-                    None => { ErrorResponseBuilder::no_auth() }
-                    Some(encoded) => {
-                        let skip = "Basic ".len();
-                        let extracted = &encoded.as_bytes()[skip..];
-                        let decoded = BASE64.decode(extracted);
-                        match decoded {
-                            Err(err) => {
-                                tracing::warn!("Failed to decode {err}");
-                                ErrorResponseBuilder::invalid_auth()
-                            }
-                            // Unpacks it with lots of error handling
-                            Ok(value) => {
-                                let decoded_str = String::from_utf8(value)
-                                    .unwrap_or(String::from("invalid:invalid"));
-                                if !decoded_str.contains(':') {
-                                    tracing::info!("User tried to authenticate with {decoded_str}, which is not a valid format");
-                                    return ErrorResponseBuilder::invalid_auth()
-                                }
-
-                                let (username, password) = decoded_str.rsplit_once(':')
-                                    .expect("Failed to split after checking delimiter exists");
-
-                                if username != maven_config.username || password != maven_config.password {
-                                    tracing::info!("User tried to authenticate with {username} and {password}, which is incorrect.");
-                                    return ErrorResponseBuilder::invalid_auth()
-                                }
-
-                                let size_header = event.headers().get("content-length");
-                                let size: i64 = match size_header {
-                                    None => { return ErrorResponseBuilder::invalid_content_length() }
-                                    Some(data) => {
-                                        let length = data.to_str();
-                                        match length {
-                                            Err(_) => { return ErrorResponseBuilder::invalid_content_length() }
-                                            Ok(data) => { data.parse().unwrap() }
-                                        }
-                                    }
-                                };
-
-                                if size > maven_config.max_artifact_size { return ErrorResponseBuilder::too_large(&maven_config) }
-
-                                return storage::upload_artifact(s3_client, maven_config, &request_path, event.body()).await
-                            }
-                        }
-                    }
-                }
+                let (identity, response) = upload(&event, s3_client, maven_config, authenticator, &request_path).await;
+                audit::record(s3_client, maven_config, &event, Action::Write, &request_path, identity.as_ref(), &response).await;
+                return response
+            }
+
+            // removes a single file, checksums and metadata catch up through the s3 events like any other change
+            if http_method == Method::DELETE {
+                let (identity, response) = delete(&event, s3_client, maven_config, authenticator, &request_path).await;
+                audit::record(s3_client, maven_config, &event, Action::Delete, &request_path, identity.as_ref(), &response).await;
+                return response
             }
 
             // not an allowed method
             ErrorResponseBuilder::invalid_request_method(http_method)
         }
     }
-}
\ No newline at end of file
+}
+
+// Who was turned away, if they said, and what to tell them
+struct Denied {
+    identity: Option<Identity>,
+    response: Result<Response<Body>, Error>
+}
+
+// Checks the credentials on the request, and that whoever they belong to is allowed to do this to the path
+async fn check_authorization(
+    event: &Request,
+    s3_client: &Client,
+    maven_config: &MavenConfig,
+    authenticator: &Authenticator,
+    action: Action,
+    path: &str
+) -> Result<Identity, Denied> {
+    let identity = match authenticator.authenticate(event, s3_client, maven_config).await {
+        Err(failure) => { return Err(Denied { identity: None, response: deny_auth(failure) }) }
+        Ok(identity) => { identity }
+    };
+
+    if !identity.can(action, path) {
+        tracing::info!("User \"{}\" is not allowed to {action} \"{path}\"", identity.name.escape_debug());
+        return Err(Denied { identity: Some(identity), response: ErrorResponseBuilder::not_permitted(action) })
+    }
+    Ok(identity)
+}
+
+// The response to a PUT, and who sent it if they got that far
+async fn upload(
+    event: &Request,
+    s3_client: &Client,
+    maven_config: &MavenConfig,
+    authenticator: &Authenticator,
+    request_path: &String
+) -> (Option<Identity>, Result<Response<Body>, Error>) {
+    if request_path.is_empty() { return (None, ErrorResponseBuilder::invalid_request()) }
+    let identity = match check_authorization(event, s3_client, maven_config, authenticator, Action::Write, request_path).await {
+        Err(denied) => { return (denied.identity, denied.response) }
+        Ok(identity) => { identity }
+    };
+    if !maven_config.allows_upload_to(request_path) { return (Some(identity), ErrorResponseBuilder::invalid_layout(request_path)) }
+
+    let size: Option<i64> = event.headers().get("content-length")
+        .and_then(|it| { it.to_str().ok() })
+        .and_then(|it| { it.parse().ok() });
+    let response = match size {
+        None => { ErrorResponseBuilder::invalid_content_length() }
+        Some(size) if size > maven_config.max_artifact_size => { ErrorResponseBuilder::too_large(maven_config) }
+        Some(_) => { storage::upload_artifact(s3_client, maven_config, request_path, event.body()).await }
+    };
+    (Some(identity), response)
+}
+
+// The response to a DELETE, and who sent it if they got that far
+async fn delete(
+    event: &Request,
+    s3_client: &Client,
+    maven_config: &MavenConfig,
+    authenticator: &Authenticator,
+    request_path: &str
+) -> (Option<Identity>, Result<Response<Body>, Error>) {
+    if request_path.is_empty() || request_path.ends_with('/') { return (None, ErrorResponseBuilder::invalid_request()) }
+    match check_authorization(event, s3_client, maven_config, authenticator, Action::Delete, request_path).await {
+        Err(denied) => { (denied.identity, denied.response) }
+        Ok(identity) => { (Some(identity), storage::delete_artifact(s3_client, maven_config, request_path).await) }
+    }
+}
+
+fn deny_auth(failure: AuthFailure) -> Result<Response<Body>, Error> {
+    match failure {
+        AuthFailure::Missing => { ErrorResponseBuilder::no_auth() }
+        AuthFailure::Invalid => { ErrorResponseBuilder::invalid_auth() }
+        AuthFailure::Throttled(retry_after) => { ErrorResponseBuilder::too_many_attempts(retry_after) }
+    }
+}
+
+// Reads are anonymous unless they reach something private, the whole repository for None
+// Credentials on a public read are ignored rather than checked, so a stale password can't get a build machine (or
+// everyone behind the same ip) throttled out of public artifacts
+async fn read_access(
+    event: &Request,
+    s3_client: &Client,
+    maven_config: &MavenConfig,
+    authenticator: &Authenticator,
+    path: Option<&str>
+) -> Result<ReadAccess, Result<Response<Body>, Error>> {
+    let private_paths = maven_config.private_paths.clone();
+    let reaches_private = match path {
+        None => { private_paths.everything || !private_paths.prefixes.is_empty() }
+        Some(path) => { private_paths.reaches(path) }
+    };
+    if !reaches_private {
+        return Ok(ReadAccess { private_paths, identity: None })
+    }
+
+    let identity = match authenticator.authenticate(event, s3_client, maven_config).await {
+        Err(AuthFailure::Throttled(retry_after)) => { return Err(ErrorResponseBuilder::too_many_attempts(retry_after)) }
+        result => { result.ok() }
+    };
+    Ok(ReadAccess { private_paths, identity })
+}
+
+// Someone that didn't say who they are gets asked to, someone that did just isn't allowed
+fn deny_read(read_access: &ReadAccess) -> Result<Response<Body>, Error> {
+    match read_access.identity {
+        None => { ErrorResponseBuilder::no_auth() }
+        Some(_) => { ErrorResponseBuilder::not_permitted(Action::Read) }
+    }
+}
+
+// Responses that depend on who asked must not end up in a shared cache in front of us
+fn finish_read(personal: bool, response: Result<Response<Body>, Error>) -> Result<Response<Body>, Error> {
+    let mut response = response?;
+    if personal {
+        response.headers_mut().insert("Cache-Control", HeaderValue::from_static("private, no-cache"));
+        response.headers_mut().insert("Vary", HeaderValue::from_static("Accept, Authorization"));
+    }
+    Ok(response)
+}
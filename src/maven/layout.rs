@@ -0,0 +1,213 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use crate::storage::checksums::CHECKSUM_EXTENSIONS;
+use crate::storage::metadata::METADATA_FILE;
+
+// What a path in the standard maven layout points at
+pub enum MavenPath {
+	Artifact(ArtifactPath),
+	Metadata(MetadataPath)
+}
+
+// "com/example/lib/1.0/lib-1.0-sources.jar.sha1" is com.example:lib:1.0, sources classifier, jar extension, sha1 checksum
+// Signatures keep the .asc as part of the extension ("jar.asc") the same way maven itself treats them
+pub struct ArtifactPath {
+	pub group_id: String,
+	pub artifact_id: String,
+	pub version: String,
+	pub classifier: Option<String>,
+	pub extension: String,
+	pub checksum: Option<String>
+}
+
+// "com/example/lib/maven-metadata.xml", or the version level one snapshots have
+pub struct MetadataPath {
+	pub checksum: Option<String>
+}
+
+impl ArtifactPath {
+	// "com/example/lib"
+	pub fn artifact_directory(&self) -> String {
+		format!("{}/{}", self.group_id.replace('.', "/"), self.artifact_id)
+	}
+
+	// "com/example/lib/1.0"
+	pub fn version_directory(&self) -> String {
+		format!("{}/{}", self.artifact_directory(), self.version)
+	}
+
+	pub fn is_signature(&self) -> bool {
+		self.extension.ends_with(".asc")
+	}
+}
+
+// None for anything that isn't a file in the maven layout, including every directory
+pub fn parse(path: &str) -> Option<MavenPath> {
+	let segments: Vec<&str> = path.split('/').collect();
+	if segments.iter().any(|it| { it.is_empty() || *it == "." || *it == ".." }) {
+		return None
+	}
+
+	let (file_name, checksum) = split_checksum(segments[segments.len() - 1]);
+	if file_name == METADATA_FILE {
+		if segments.len() < 2 {
+			return None
+		}
+		return Some(MavenPath::Metadata(MetadataPath { checksum }))
+	}
+
+	// at least a one segment group, an artifact, a version and a file name
+	if segments.len() < 4 {
+		return None
+	}
+	let version = segments[segments.len() - 2];
+	let artifact_id = segments[segments.len() - 3];
+	let (classifier, extension) = split_file_name(file_name, artifact_id, version)?;
+
+	Some(MavenPath::Artifact(ArtifactPath {
+		group_id: segments[..segments.len() - 3].join("."),
+		artifact_id: String::from(artifact_id),
+		version: String::from(version),
+		classifier,
+		extension,
+		checksum
+	}))
+}
+
+// Whether a request is for a file rather than a directory listing
+// Paths outside the layout (the indexer export, catalogs) still count if the name ends in a plain extension, but
+// "com/example/1.2.3" is a version directory, not a file with a "3" extension
+// That's only a guess for "com/example/lib.core", so a missing file outside the layout still gets checked for a directory
+pub fn is_file_path(path: &str) -> bool {
+	static PLAIN_FILE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[^0-9].*\.[A-Za-z][A-Za-z0-9]*$").unwrap());
+	if path.ends_with('/') {
+		return false
+	}
+
+	parse(path).is_some() || PLAIN_FILE.is_match(path.rsplit('/').next().unwrap_or(path))
+}
+
+fn split_checksum(file_name: &str) -> (&str, Option<String>) {
+	for extension in CHECKSUM_EXTENSIONS {
+		if let Some(checksummed) = file_name.strip_suffix(extension).and_then(|it| { it.strip_suffix('.') }) {
+			return (checksummed, Some(String::from(extension)))
+		}
+	}
+	(file_name, None)
+}
+
+// Splits "lib-1.0-sources.jar" into (Some("sources"), "jar"), snapshots may use a timestamp in place of SNAPSHOT
+fn split_file_name(file_name: &str, artifact_id: &str, version: &str) -> Option<(Option<String>, String)> {
+	static TIMESTAMP: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-\d{8}\.\d{6}-\d+").unwrap());
+
+	let mut rest = file_name.strip_prefix(artifact_id)?.strip_prefix('-')?;
+	match rest.strip_prefix(version) {
+		Some(after_version) => { rest = after_version }
+		None => {
+			let base = version.strip_suffix("-SNAPSHOT")?;
+			let after_base = rest.strip_prefix(base)?;
+			let timestamp = TIMESTAMP.find(after_base)?;
+			rest = &after_base[timestamp.end()..];
+		}
+	}
+
+	let (classifier, extension) = match rest.strip_prefix('.') {
+		Some(extension) => { (None, extension) }
+		None => {
+			let (classifier, extension) = rest.strip_prefix('-')?.split_once('.')?;
+			(Some(String::from(classifier)), extension)
+		}
+	};
+
+	if extension.is_empty() || classifier.as_ref().is_some_and(|it| { it.is_empty() }) {
+		return None
+	}
+	Some((classifier, String::from(extension)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn artifact(path: &str) -> ArtifactPath {
+		match parse(path) {
+			Some(MavenPath::Artifact(artifact)) => { artifact }
+			_ => { panic!("{path} should be an artifact") }
+		}
+	}
+
+	#[test]
+	fn parses_a_release() {
+		let artifact = artifact("com/example/lib/1.0/lib-1.0.jar");
+		assert_eq!(artifact.group_id, "com.example");
+		assert_eq!(artifact.artifact_id, "lib");
+		assert_eq!(artifact.version, "1.0");
+		assert_eq!(artifact.classifier, None);
+		assert_eq!(artifact.extension, "jar");
+		assert_eq!(artifact.checksum, None);
+		assert_eq!(artifact.version_directory(), "com/example/lib/1.0");
+	}
+
+	#[test]
+	fn parses_classifiers_checksums_and_signatures() {
+		let sources = artifact("com/example/lib/1.0/lib-1.0-sources.jar.sha1");
+		assert_eq!(sources.classifier.as_deref(), Some("sources"));
+		assert_eq!(sources.extension, "jar");
+		assert_eq!(sources.checksum.as_deref(), Some("sha1"));
+
+		let signature = artifact("com/example/lib/1.0/lib-1.0.pom.asc");
+		assert_eq!(signature.extension, "pom.asc");
+		assert!(signature.is_signature());
+
+		// the extension is everything after the first dot, classifiers can't have one
+		let archive = artifact("com/example/lib/1.0/lib-1.0-dist.tar.gz");
+		assert_eq!(archive.classifier.as_deref(), Some("dist"));
+		assert_eq!(archive.extension, "tar.gz");
+	}
+
+	#[test]
+	fn parses_snapshots() {
+		let plain = artifact("com/example/lib/1.0-SNAPSHOT/lib-1.0-SNAPSHOT.jar");
+		assert_eq!(plain.version, "1.0-SNAPSHOT");
+
+		let timestamped = artifact("com/example/lib/1.0-SNAPSHOT/lib-1.0-20231012.120000-1-javadoc.jar");
+		assert_eq!(timestamped.version, "1.0-SNAPSHOT");
+		assert_eq!(timestamped.classifier.as_deref(), Some("javadoc"));
+		assert_eq!(timestamped.extension, "jar");
+		assert!(parse("com/example/lib/1.0-SNAPSHOT/lib-1.0-2023.jar").is_none());
+	}
+
+	#[test]
+	fn parses_metadata() {
+		assert!(matches!(parse("com/example/lib/maven-metadata.xml"), Some(MavenPath::Metadata(MetadataPath { checksum: None }))));
+		assert!(matches!(parse("com/example/lib/1.0-SNAPSHOT/maven-metadata.xml.md5"), Some(MavenPath::Metadata(MetadataPath { checksum: Some(_) }))));
+		assert!(parse("maven-metadata.xml").is_none());
+	}
+
+	#[test]
+	fn refuses_files_outside_the_layout() {
+		for path in [
+			"com/example/lib/1.0/lib-1.1.jar",
+			"com/example/lib/1.0/other-1.0.jar",
+			"com/example/lib/1.0/lib-1.0",
+			"com/example/lib/1.0/lib-1.0-.jar",
+			"lib/1.0/lib-1.0.jar",
+			"com/example/lib/1.0/",
+			"com//lib/1.0/lib-1.0.jar",
+			"com/../lib/1.0/lib-1.0.jar"
+		] {
+			assert!(parse(path).is_none(), "{path} should be refused");
+		}
+	}
+
+	#[test]
+	fn tells_files_from_directories() {
+		assert!(is_file_path("com/example/lib/1.0/lib-1.0.jar"));
+		assert!(is_file_path(".index/nexus-maven-repository-index.gz"));
+		assert!(!is_file_path("com/example/lib/1.2.3"));
+		// only a guess without the slash, a missing "file" like this still gets checked for a directory
+		assert!(is_file_path("com/example/lib.core"));
+		assert!(!is_file_path("com/example/lib.core/"));
+		assert!(!is_file_path(""));
+	}
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use roxmltree::Node;
+use serde::Serialize;
+
+// The parts of a pom worth showing on a version page, everything else is left to whoever downloads it
+#[derive(Serialize)]
+pub struct Pom {
+	pub group_id: String,
+	pub artifact_id: String,
+	pub version: String,
+	pub packaging: String,
+	pub name: Option<String>,
+	pub description: Option<String>,
+	pub url: Option<String>,
+	pub licenses: Vec<License>,
+	pub scm: Option<Scm>,
+	pub developers: Vec<Developer>,
+	pub dependencies: Vec<Dependency>
+}
+
+#[derive(Serialize)]
+pub struct License {
+	pub name: Option<String>,
+	pub url: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct Scm {
+	pub url: Option<String>,
+	pub connection: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct Developer {
+	pub id: Option<String>,
+	pub name: Option<String>,
+	pub email: Option<String>,
+	pub url: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct Dependency {
+	pub group_id: String,
+	pub artifact_id: String,
+	pub version: Option<String>,
+	pub scope: Option<String>,
+	pub optional: bool
+}
+
+impl Pom {
+	// None if it isn't a pom at all, missing sections just come out empty
+	pub fn parse(xml: &str) -> Option<Pom> {
+		let document = roxmltree::Document::parse(xml).ok()?;
+		let project = document.root_element();
+		if !project.has_tag_name("project") {
+			return None
+		}
+
+		// group and version are inherited from the parent when they aren't set
+		let parent = child(project, "parent");
+		let inherited = |name: &str| {
+			child_text(project, name).or_else(|| { parent.and_then(|it| { child_text(it, name) }) })
+		};
+		let group_id = String::from(inherited("groupId")?);
+		let artifact_id = String::from(child_text(project, "artifactId")?);
+		let version = String::from(inherited("version")?);
+
+		let mut properties: HashMap<String, String> = HashMap::new();
+		if let Some(node) = child(project, "properties") {
+			for property in node.children().filter(|it| { it.is_element() }) {
+				properties.insert(String::from(property.tag_name().name()), String::from(property.text().unwrap_or_default().trim()));
+			}
+		}
+		properties.insert(String::from("project.groupId"), group_id.clone());
+		properties.insert(String::from("project.artifactId"), artifact_id.clone());
+		properties.insert(String::from("project.version"), version.clone());
+		let text = |node: Node, name: &str| { child_text(node, name).map(|it| { interpolate(it, &properties) }) };
+
+		let licenses = children(project, "licenses", "license").into_iter().map(|it| {
+			License {
+				name: text(it, "name"),
+				url: text(it, "url")
+			}
+		}).collect();
+
+		let scm = child(project, "scm").map(|it| {
+			Scm {
+				url: text(it, "url"),
+				connection: text(it, "connection").or_else(|| { text(it, "developerConnection") })
+			}
+		});
+
+		let developers = children(project, "developers", "developer").into_iter().map(|it| {
+			Developer {
+				id: text(it, "id"),
+				name: text(it, "name"),
+				email: text(it, "email"),
+				url: text(it, "url")
+			}
+		}).collect();
+
+		// only the ones declared directly, dependencyManagement is just version hints for children
+		let dependencies = children(project, "dependencies", "dependency").into_iter().filter_map(|it| {
+			Some(Dependency {
+				group_id: text(it, "groupId")?,
+				artifact_id: text(it, "artifactId")?,
+				version: text(it, "version"),
+				scope: text(it, "scope"),
+				optional: child_text(it, "optional") == Some("true")
+			})
+		}).collect();
+
+		Some(Pom {
+			packaging: text(project, "packaging").unwrap_or(String::from("jar")),
+			name: text(project, "name"),
+			description: text(project, "description"),
+			url: text(project, "url"),
+			group_id,
+			artifact_id,
+			version,
+			licenses,
+			scm,
+			developers,
+			dependencies
+		})
+	}
+}
+
+// Replaces ${property} with what the pom sets it to, anything we don't know about is left alone
+fn interpolate(value: &str, properties: &HashMap<String, String>) -> String {
+	let mut result = String::with_capacity(value.len());
+	let mut rest = value;
+	while let Some(start) = rest.find("${") {
+		let end = match rest[start..].find('}') {
+			None => { break }
+			Some(end) => { start + end }
+		};
+		let name = &rest[start + 2..end];
+		result.push_str(&rest[..start]);
+		match properties.get(name) {
+			Some(property) => { result.push_str(property) }
+			None => { result.push_str(&rest[start..=end]) }
+		}
+		rest = &rest[end + 1..];
+	}
+	result.push_str(rest);
+	result
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+	node.children().find(|it| { it.has_tag_name(name) })
+}
+
+// "licenses" > "license" style lists
+fn children<'a, 'input>(node: Node<'a, 'input>, list: &str, name: &str) -> Vec<Node<'a, 'input>> {
+	match child(node, list) {
+		None => { Vec::new() }
+		Some(list) => { list.children().filter(|it| { it.has_tag_name(name) }).collect() }
+	}
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+	child(node, name)
+		.and_then(|it| { it.text() })
+		.map(|it| { it.trim() })
+		.filter(|it| { !it.is_empty() })
+}
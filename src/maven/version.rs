@@ -0,0 +1,252 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+// Maven's ComparableVersion ordering, so "1.10" > "1.9" and "1.0-rc1" < "1.0" < "1.0-sp1"
+// Versions are split on '.', '-' and digit/letter transitions, a '-' (or a transition) starts a
+// nested list so qualifiers sort below the release they belong to
+pub struct MavenVersion {
+	original: String,
+	items: Vec<Item>
+}
+
+enum Item {
+	Int(String),
+	Str(String),
+	List(Vec<Item>)
+}
+
+// Known qualifiers in release order, the empty qualifier is the release itself
+// Anything unknown sorts after all of these, alphabetically
+const QUALIFIERS: [&str; 7] = ["alpha", "beta", "milestone", "rc", "snapshot", "", "sp"];
+
+impl MavenVersion {
+	pub fn parse(version: &str) -> MavenVersion {
+		let lowered = version.to_lowercase();
+
+		// stack of the lists we are nested in, the last one is what we are currently appending to
+		let mut stack: Vec<Vec<Item>> = vec![Vec::new()];
+		let mut current = String::new();
+		let mut is_digit = false;
+
+		for character in lowered.chars() {
+			if character == '.' || character == '-' {
+				let item = if current.is_empty() { Item::Int(String::from("0")) } else { parse_item(is_digit, &current, false) };
+				stack.last_mut().unwrap().push(item);
+				current.clear();
+
+				if character == '-' {
+					stack.push(Vec::new());
+				}
+			} else if character.is_ascii_digit() {
+				if !is_digit && !current.is_empty() {
+					stack.last_mut().unwrap().push(parse_item(false, &current, true));
+					current.clear();
+					stack.push(Vec::new());
+				}
+				is_digit = true;
+				current.push(character);
+			} else {
+				if is_digit && !current.is_empty() {
+					stack.last_mut().unwrap().push(parse_item(true, &current, false));
+					current.clear();
+					stack.push(Vec::new());
+				}
+				is_digit = false;
+				current.push(character);
+			}
+		}
+
+		if !current.is_empty() {
+			stack.last_mut().unwrap().push(parse_item(is_digit, &current, false));
+		}
+
+		// fold the nested lists back together, normalizing each on the way out
+		while stack.len() > 1 {
+			let mut list = stack.pop().unwrap();
+			normalize(&mut list);
+			stack.last_mut().unwrap().push(Item::List(list));
+		}
+		let mut items = stack.pop().unwrap();
+		normalize(&mut items);
+
+		MavenVersion {
+			original: String::from(version),
+			items
+		}
+	}
+
+	pub fn is_snapshot(&self) -> bool {
+		self.original.ends_with("-SNAPSHOT")
+	}
+}
+
+fn parse_item(is_digit: bool, buffer: &str, followed_by_digit: bool) -> Item {
+	if is_digit {
+		let trimmed = buffer.trim_start_matches('0');
+		return Item::Int(String::from(if trimmed.is_empty() { "0" } else { trimmed }))
+	}
+
+	let value = match buffer {
+		"a" if followed_by_digit => { "alpha" }
+		"b" if followed_by_digit => { "beta" }
+		"m" if followed_by_digit => { "milestone" }
+		"ga" | "final" | "release" => { "" }
+		"cr" => { "rc" }
+		_ => { buffer }
+	};
+	Item::Str(String::from(value))
+}
+
+// Trailing "nothing" items (0, "", ga, final) dont change what a version means, so drop them
+fn normalize(list: &mut Vec<Item>) {
+	let mut index = list.len();
+	while index > 0 {
+		index -= 1;
+		if list[index].is_null() {
+			list.remove(index);
+		} else if !matches!(list[index], Item::List(_)) {
+			break
+		}
+	}
+}
+
+fn comparable_qualifier(qualifier: &str) -> String {
+	match QUALIFIERS.iter().position(|it| { *it == qualifier }) {
+		Some(position) => { position.to_string() }
+		None => { format!("{}-{qualifier}", QUALIFIERS.len()) }
+	}
+}
+
+fn release_qualifier() -> String {
+	comparable_qualifier("")
+}
+
+impl Item {
+	fn is_null(&self) -> bool {
+		match self {
+			Item::Int(value) => { value == "0" }
+			Item::Str(value) => { value.is_empty() }
+			Item::List(items) => { items.is_empty() }
+		}
+	}
+
+	// How this item compares against a missing item, "1.0" vs "1.0.1" compares 1 against nothing
+	fn compare_to_none(&self) -> Ordering {
+		match self {
+			Item::Int(value) => { if value == "0" { Ordering::Equal } else { Ordering::Greater } }
+			Item::Str(value) => { comparable_qualifier(value).cmp(&release_qualifier()) }
+			Item::List(items) => {
+				match items.first() {
+					None => { Ordering::Equal }
+					Some(first) => { first.compare_to_none() }
+				}
+			}
+		}
+	}
+
+	fn compare(&self, other: &Item) -> Ordering {
+		match (self, other) {
+			(Item::Int(left), Item::Int(right)) => { left.len().cmp(&right.len()).then_with(|| { left.cmp(right) }) }
+			(Item::Int(_), _) => { Ordering::Greater }
+			(Item::Str(_), Item::Int(_)) => { Ordering::Less }
+			(Item::Str(left), Item::Str(right)) => { comparable_qualifier(left).cmp(&comparable_qualifier(right)) }
+			(Item::Str(_), Item::List(_)) => { Ordering::Less }
+			(Item::List(_), Item::Int(_)) => { Ordering::Less }
+			(Item::List(_), Item::Str(_)) => { Ordering::Greater }
+			(Item::List(left), Item::List(right)) => { compare_lists(left, right) }
+		}
+	}
+}
+
+fn compare_lists(left: &[Item], right: &[Item]) -> Ordering {
+	for index in 0..left.len().max(right.len()) {
+		let ordering = match (left.get(index), right.get(index)) {
+			(Some(left), Some(right)) => { left.compare(right) }
+			(Some(left), None) => { left.compare_to_none() }
+			(None, Some(right)) => { right.compare_to_none().reverse() }
+			(None, None) => { Ordering::Equal }
+		};
+
+		if ordering != Ordering::Equal {
+			return ordering
+		}
+	}
+	Ordering::Equal
+}
+
+impl Display for MavenVersion {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+		formatter.write_str(&self.original)
+	}
+}
+
+impl Ord for MavenVersion {
+	fn cmp(&self, other: &Self) -> Ordering {
+		compare_lists(&self.items, &other.items)
+	}
+}
+
+impl PartialOrd for MavenVersion {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for MavenVersion {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for MavenVersion {}
+
+// Orders version strings oldest first
+pub fn compare_versions(left: &str, right: &str) -> Ordering {
+	MavenVersion::parse(left).cmp(&MavenVersion::parse(right))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_ascending(versions: &[&str]) {
+		for pair in versions.windows(2) {
+			assert_eq!(compare_versions(pair[0], pair[1]), Ordering::Less, "{} should sort before {}", pair[0], pair[1]);
+			assert_eq!(compare_versions(pair[1], pair[0]), Ordering::Greater, "{} should sort after {}", pair[1], pair[0]);
+		}
+	}
+
+	#[test]
+	fn orders_qualifiers_around_the_release() {
+		assert_ascending(&["1.0-alpha", "1.0-beta", "1.0-milestone", "1.0-rc", "1.0-SNAPSHOT", "1.0", "1.0-sp", "1.0-unknown"]);
+		assert_ascending(&["1.0-alpha-1", "1.0-alpha-2", "1.0-rc1", "1.0-rc2", "1.0"]);
+	}
+
+	#[test]
+	fn orders_numbers_by_value() {
+		assert_ascending(&["1.0", "1.0.1", "1.1", "1.9", "1.10", "2", "10.0"]);
+		assert_ascending(&["1.0", "1.0-1", "1.0.1"]);
+	}
+
+	#[test]
+	fn treats_trailing_zeros_and_release_qualifiers_as_the_release() {
+		for (left, right) in [("1.0", "1.0.0"), ("1", "1.0.0"), ("1.0", "1.0-ga"), ("1.0", "1.0-final"), ("1.0", "1.0-release"), ("1.01", "1.1")] {
+			assert_eq!(compare_versions(left, right), Ordering::Equal, "{left} should equal {right}");
+		}
+	}
+
+	#[test]
+	fn expands_short_qualifiers() {
+		for (left, right) in [("1.0-a1", "1.0-alpha-1"), ("1.0-b2", "1.0-beta-2"), ("1.0-m3", "1.0-milestone-3"), ("1.0-cr1", "1.0-rc1"), ("1.0-RC1", "1.0-rc1")] {
+			assert_eq!(compare_versions(left, right), Ordering::Equal, "{left} should equal {right}");
+		}
+	}
+
+	#[test]
+	fn keeps_the_original_text() {
+		let version = MavenVersion::parse("1.0.0-SNAPSHOT");
+		assert!(version.is_snapshot());
+		assert_eq!(version.to_string(), "1.0.0-SNAPSHOT");
+		assert!(!MavenVersion::parse("1.0.0").is_snapshot());
+	}
+}
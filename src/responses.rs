@@ -1,2 +1,7 @@
 pub mod build_response;
-mod http_templates;
\ No newline at end of file
+mod http_templates;
+mod json_templates;
+mod snippet_templates;
+mod svg_templates;
+
+pub use http_templates::Templates;
\ No newline at end of file
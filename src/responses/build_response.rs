@@ -4,15 +4,31 @@ use aws_sdk_s3::primitives::DateTimeFormat;
 use http::Method;
 use lambda_http::{Body, Response};
 use lambda_runtime::Error;
+use crate::auth::ReadAccess;
+use crate::auth::tokens;
+use crate::auth::tokens::NewToken;
+use crate::auth::rules::Action;
 use crate::cfg::MavenConfig;
-use crate::responses::http_templates;
+use crate::badge::BadgeQuery;
+use crate::latest;
+use crate::latest::LatestQuery;
+use crate::responses::{http_templates, json_templates, snippet_templates, svg_templates, Templates};
+use crate::search;
+use crate::search::SearchQuery;
 use crate::storage;
-use crate::storage::layers::Layer;
-use crate::util::{mime_type, simple_response, simple_response_fmt};
+use crate::storage::audit;
+use crate::storage::audit::AuditQuery;
+use crate::storage::layers::{Layer, LayerSort};
+use crate::storage::repository_index;
+use crate::util::{encode_path, encode_path_segment, escape_xml, mime_type, simple_response, simple_response_fmt};
 
 pub struct ErrorResponseBuilder {}
 pub struct ResponseBuilder {}
 
+// Our pages never need scripts, so even if something gets past the escaping it can't run
+// Inline styles are allowed for the accent color in the base template
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; img-src * data:; style-src 'unsafe-inline'; form-action 'self'; base-uri 'none'; frame-ancestors 'none'";
+
 // I originally did this because it made more sense to my OOP brain but somehow it breaks the
 // lambda_http::run in main if you try to move these out so they sit in an empty impl
 impl ErrorResponseBuilder {
@@ -24,6 +40,10 @@ impl ErrorResponseBuilder {
 		simple_response(400, "Invalid request.")
 	}
 
+	pub fn invalid_path() -> Result<Response<Body>, Error> {
+		simple_response(400, "Invalid path. Paths can't be empty between slashes, contain . or .. segments, encoded slashes or control characters.")
+	}
+
 	pub fn invalid_request_method(http_method: Method) -> Result<Response<Body>, Error> {
 		simple_response_fmt(400, format!("Method {http_method} not supported."))
 	}
@@ -38,6 +58,14 @@ impl ErrorResponseBuilder {
 		Ok(resp)
 	}
 
+	pub fn no_search_index() -> Result<Response<Body>, Error> {
+		simple_response(503, "The search index hasn't been built yet. Set up s3 event notifications or POST api/index/rebuild to build it.")
+	}
+
+	pub fn no_tokens_allowed() -> Result<Response<Body>, Error> {
+		simple_response(404, "API tokens aren't used when the gateway authenticates callers.")
+	}
+
 	pub fn no_content() -> Result<Response<Body>, Error> {
 		simple_response(404, "No content found.")
 	}
@@ -55,7 +83,7 @@ impl ErrorResponseBuilder {
 		let resp = Response::builder()
 			.status(401)
 			.header("content-type", "text/html")
-			.header("WWW-Authenticate", "Basic realm=\"Upload Artifact\"")
+			.header("WWW-Authenticate", "Basic realm=\"Maven Repository\", charset=\"UTF-8\"")
 			.body(Body::Text(String::from("No authorization provided.")))
 			.map_err(Box::new)?;
 		Ok(resp)
@@ -65,6 +93,20 @@ impl ErrorResponseBuilder {
 		simple_response(403, "Invalid authorization provided.")
 	}
 
+	pub fn not_permitted(action: Action) -> Result<Response<Body>, Error> {
+		simple_response_fmt(403, format!("You are not allowed to {action} this path."))
+	}
+
+	pub fn too_many_attempts(retry_after: i64) -> Result<Response<Body>, Error> {
+		let resp = Response::builder()
+			.status(429)
+			.header("content-type", "text/html")
+			.header("Retry-After", retry_after.max(1))
+			.body(Body::Text(String::from("Too many failed logins, try again later.")))
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
 	pub fn invalid_content_length() -> Result<Response<Body>, Error> {
 		simple_response(411, "No content-length provided.")
 	}
@@ -72,13 +114,22 @@ impl ErrorResponseBuilder {
 	pub fn too_large(maven_config: &MavenConfig) -> Result<Response<Body>, Error> {
 		simple_response_fmt(413, format!("Artifact too large. Max size: {}", maven_config.max_artifact_size))
 	}
+
+	pub fn invalid_layout(request_path: &str) -> Result<Response<Body>, Error> {
+		simple_response_fmt(400, format!(
+			"\"{}\" does not follow the maven layout. Uploads have to be \
+			group/path/artifact/version/artifact-version[-classifier].extension, \
+			a checksum (.md5, .sha1, .sha256, .sha512) or signature (.asc) of one, or a maven-metadata.xml.",
+			escape_xml(request_path)
+		))
+	}
 }
 
 impl ResponseBuilder {
-	pub async fn resource_head(s3_client: &Client, maven_config: MavenConfig, request_path: &String) -> Result<Response<Body>, Error> {
+	pub async fn resource_head(s3_client: &Client, maven_config: &MavenConfig, request_path: &String) -> Result<Response<Body>, Error> {
 		tracing::info!("Getting object head \"{request_path}\"");
 		let obj = s3_client.head_object()
-			.bucket(maven_config.bucket_name)
+			.bucket(&maven_config.bucket_name)
 			.key(request_path)
 			.send().await;
 
@@ -100,7 +151,7 @@ impl ResponseBuilder {
 		}
 	}
 
-	pub async fn resource(s3_client: &Client, maven_config: MavenConfig, request_path: &String) -> Result<Response<Body>, Error> {
+	pub async fn resource(s3_client: &Client, maven_config: &MavenConfig, request_path: &str) -> Result<Response<Body>, Error> {
 		let resource = storage::get_resource(s3_client, maven_config, request_path).await;
 		match resource {
 			None => {
@@ -124,20 +175,200 @@ impl ResponseBuilder {
 		}
 	}
 
-	pub async fn index<'a>(s3_client: &Client, maven_config: MavenConfig, root_layer: &Arc<Mutex<Layer>>, request_path: &str) -> Result<Response<Body>, Error> {
-		let layer = storage::get_index(s3_client, maven_config, root_layer, request_path).await;
+	#[allow(clippy::too_many_arguments)]
+	pub async fn index(s3_client: &Client, maven_config: &MavenConfig, templates: &Templates, root_layer: &Arc<Mutex<Layer>>, read_access: &ReadAccess, request_path: &str, base_url: &str, as_json: bool, sort: LayerSort) -> Result<Response<Body>, Error> {
+		let mut layer = match storage::get_index(s3_client, maven_config, root_layer, request_path).await {
+			None => { return ErrorResponseBuilder::no_content() }
+			Some(layer) => { layer }
+		};
+		layer.sort(sort);
+		// private directories aren't even listed for people that can't open them
+		layer.packages.retain(|it| { read_access.can_read(&format!("{request_path}{}/", it.name)) });
+
+		let req_slice = request_path.split('/').filter(|it| { !it.is_empty() }).collect();
+		let (content_type, body) = if as_json {
+			("application/json", json_templates::build_index(&req_slice, &layer))
+		} else if let Some(pom) = storage::get_pom(s3_client, maven_config, &req_slice, &layer).await {
+			// without an index the dependencies just aren't linked, reads never build one
+			let index = repository_index::load(s3_client, maven_config).await.unwrap_or_default();
+			("text/html", http_templates::build_version(templates, &req_slice, &layer, &pom, &index, &snippet_templates::build_snippets(maven_config, &pom, base_url))?)
+		} else {
+			("text/html", http_templates::build_index(templates, &req_slice, &layer)?)
+		};
+
+		let resp = Response::builder()
+			.status(200)
+			.header("content-type", content_type)
+			.header("Cache-Control", "public, max-age=43200")
+			.header("Vary", "Accept")
+			.header("Content-Security-Policy", CONTENT_SECURITY_POLICY)
+			.header("X-Content-Type-Options", "nosniff")
+			.body(Body::Text(body))
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
 
-		if layer.is_none() {
+	// Only redirects to directories that exist, anything else stays a 404 rather than bouncing around
+	pub async fn directory_redirect(s3_client: &Client, maven_config: &MavenConfig, root_layer: &Arc<Mutex<Layer>>, request_path: &str, query: Option<&str>) -> Result<Response<Body>, Error> {
+		if storage::get_index(s3_client, maven_config, root_layer, &format!("{request_path}/")).await.is_none() {
 			return ErrorResponseBuilder::no_content()
 		}
 
+		// relative to the request, "lib" -> "lib/", so the stage and domain dont matter
+		let directory = request_path.rsplit('/').next().unwrap_or(request_path);
+		let query = query.filter(|it| { !it.is_empty() }).map(|it| { format!("?{it}") }).unwrap_or_default();
+		let resp = Response::builder()
+			.status(301)
+			.header("Location", format!("./{}/{query}", encode_path_segment(directory)))
+			.header("Cache-Control", "public, max-age=3600")
+			.body(Body::Empty)
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	pub async fn search(s3_client: &Client, maven_config: &MavenConfig, templates: &Templates, read_access: &ReadAccess, query: &SearchQuery, as_json: bool) -> Result<Response<Body>, Error> {
+		let results = if query.is_empty() {
+			Vec::new()
+		} else {
+			// building the index lists the whole bucket, that's left to events and api/index/rebuild rather than anyone searching
+			match repository_index::load(s3_client, maven_config).await {
+				None => { return ErrorResponseBuilder::no_search_index() }
+				Some(index) => { search::search(&index, query, read_access) }
+			}
+		};
+
+		let (content_type, body) = if as_json {
+			("application/json", json_templates::build_search(&results))
+		} else {
+			("text/html", http_templates::build_search(templates, query, &results)?)
+		};
+
 		let resp = Response::builder()
 			.status(200)
-			.header("content-type", "text/html")
-			.header("Cache-Control", "public, max-age=43200")
-			.body(Body::Text(http_templates::build_index(
-				&request_path.split('/').filter(|it| { !it.is_empty() }).collect(),
-				&layer.unwrap())))
+			.header("content-type", content_type)
+			.header("Cache-Control", "public, max-age=300")
+			.header("Content-Security-Policy", CONTENT_SECURITY_POLICY)
+			.header("X-Content-Type-Options", "nosniff")
+			.body(Body::Text(body))
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	pub async fn latest(s3_client: &Client, maven_config: &MavenConfig, query: &LatestQuery) -> Result<Response<Body>, Error> {
+		let version = match latest::resolve_version(s3_client, maven_config, &query.artifact_directory, query.snapshots).await {
+			None => { return ErrorResponseBuilder::no_content() }
+			Some(version) => { version }
+		};
+
+		if query.redirect {
+			let key = latest::resolve_file(s3_client, maven_config, query, &version).await;
+
+			// relative to /api/latest, so it still works behind a stage or a custom domain
+			let resp = Response::builder()
+				.status(302)
+				.header("Location", format!("../{}", encode_path(&key)))
+				.header("Cache-Control", "public, max-age=300")
+				.body(Body::Empty)
+				.map_err(Box::new)?;
+			return Ok(resp)
+		}
+
+		let body = json_templates::build_latest(&query.group_id, &query.artifact_id, &version);
+		let resp = Response::builder()
+			.status(200)
+			.header("content-type", "application/json")
+			.header("Cache-Control", "public, max-age=300")
+			.body(Body::Text(body))
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	// Always renders something, a broken image in a readme is worse than a badge saying it wasn't found
+	pub async fn badge(s3_client: &Client, maven_config: &MavenConfig, query: &BadgeQuery) -> Result<Response<Body>, Error> {
+		let version = latest::resolve_version(s3_client, maven_config, &query.artifact_directory, query.snapshots).await;
+
+		let (value, default_color) = match &version {
+			None => { (String::from("not found"), "#9f9f9f") }
+			Some(version) if version.ends_with("-SNAPSHOT") => { (format!("v{version}"), "#fe7d37") }
+			Some(version) => { (format!("v{version}"), "#007ec6") }
+		};
+		let color = query.color.as_deref().unwrap_or(default_color);
+
+		// short lived so githubs image proxy picks up new releases, it refetches once this runs out
+		let resp = Response::builder()
+			.status(200)
+			.header("content-type", "image/svg+xml")
+			.header("Cache-Control", "public, max-age=300, s-maxage=300")
+			.body(Body::Text(svg_templates::build_badge(&query.label, &value, color, query.style)))
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	pub async fn tokens(s3_client: &Client, maven_config: &MavenConfig, owner: &str) -> Result<Response<Body>, Error> {
+		let tokens = tokens::list(s3_client, maven_config, owner).await?;
+		let resp = Response::builder()
+			.status(200)
+			.header("content-type", "application/json")
+			.header("Cache-Control", "no-store")
+			.body(Body::Text(json_templates::build_tokens(&tokens)))
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	pub async fn create_token(s3_client: &Client, maven_config: &MavenConfig, owner: &str, body: &Body) -> Result<Response<Body>, Error> {
+		let new_token: NewToken = match serde_json::from_slice(body.as_ref()) {
+			Err(err) => { return simple_response_fmt(400, format!("Invalid token request. {}", escape_xml(&err.to_string()))) }
+			Ok(new_token) => { new_token }
+		};
+		if let Some(problem) = new_token.problem() {
+			return simple_response_fmt(400, escape_xml(&problem))
+		}
+
+		let (stored, token) = tokens::create(s3_client, maven_config, owner, new_token).await?;
+		let resp = Response::builder()
+			.status(201)
+			.header("content-type", "application/json")
+			.header("Cache-Control", "no-store")
+			.body(Body::Text(json_templates::build_created_token(&stored, &token)))
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	pub async fn revoke_token(s3_client: &Client, maven_config: &MavenConfig, owner: &str, id: &str) -> Result<Response<Body>, Error> {
+		if !tokens::revoke(s3_client, maven_config, owner, id).await? {
+			return ErrorResponseBuilder::no_content()
+		}
+
+		let resp = Response::builder()
+			.status(204)
+			.body(Body::Empty)
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	pub async fn audit(s3_client: &Client, maven_config: &MavenConfig, query: &AuditQuery) -> Result<Response<Body>, Error> {
+		let events = audit::query(s3_client, maven_config, query).await?;
+		let resp = Response::builder()
+			.status(200)
+			.header("content-type", "application/json")
+			.header("Cache-Control", "no-store")
+			.body(Body::Text(json_templates::build_audit(&events)))
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	pub fn rebuilt_index() -> Result<Response<Body>, Error> {
+		let resp = Response::builder()
+			.status(204)
+			.body(Body::Empty)
+			.map_err(Box::new)?;
+		Ok(resp)
+	}
+
+	pub fn deleted_artifact() -> Result<Response<Body>, Error> {
+		let resp = Response::builder()
+			.status(204)
+			.body(Body::Empty)
 			.map_err(Box::new)?;
 		Ok(resp)
 	}
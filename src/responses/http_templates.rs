@@ -1,41 +1,229 @@
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use lambda_runtime::Error;
+use minijinja::{context, AutoEscape, Environment, Value};
+use serde::Serialize;
+use crate::cfg::MavenConfig;
+use crate::maven::pom::Pom;
+use crate::responses::snippet_templates::Snippet;
+use crate::search::{SearchQuery, SearchResult};
+use crate::storage;
 use crate::storage::layers::Layer;
+use crate::storage::repository_index::RepositoryIndex;
+use crate::util::{encode_path, encode_path_segment};
 
-// Could probably be refactored out to a proper .html template at some point
-pub fn build_index(req_slice: &Vec<&str>, layer: &Layer) -> String {
-	let full_path = req_slice.join("/") + "/";
-	let mut builder = string_builder::Builder::new(512);
-	builder.append("<!DOCTYPE html>\n");
-	builder.append("<html>\n");
-	builder.append("	<head>\n");
-	builder.append("		<title>Silver's Silly Little Maven</title>\n");
-	builder.append("		<meta property=\"og:title\" content=\"Silver's Silly Little Maven\">\n");
-	builder.append(format!("		<meta property=\"og:description\" content=\"An index of {full_path} on the maven\">\n"));
-	builder.append("		<meta property=\"og:image\" content=\"https://www.silverandro.dev/site_image.png\">\n");
-	builder.append("		<meta name=\"theme-color\" content=\"#B00B69\">\n");
-	builder.append("		<link rel=\"icon\" type=\"image/x-icon\" href=\"https://silverandro.dev/favicon.ico\">");
-	builder.append("	</head>\n");
-	builder.append("	<body>\n");
-	builder.append(format!("		<h1>Index of {full_path}</h1>\n"));
-
-	if !layer.packages.is_empty() {
-		builder.append("		<h3>Packages:</h3>\n");
-		builder.append("		<ul>\n");
-		for package in &layer.packages {
-			builder.append(format!("			<li><a href=\"./{package}/\">{package}/</a></li>\n"))
+// A deployment can replace any of these by putting its own version under this prefix in the bucket
+pub const TEMPLATE_PREFIX: &str = ".maven/templates/";
+
+const DEFAULT_TEMPLATES: [(&str, &str); 4] = [
+	("base.html", include_str!("templates/base.html")),
+	("index.html", include_str!("templates/index.html")),
+	("search.html", include_str!("templates/search.html")),
+	("version.html", include_str!("templates/version.html"))
+];
+
+// Loaded once per instance, pages are rendered from these with the branding from the config available as `brand`
+pub struct Templates {
+	environment: Environment<'static>
+}
+
+impl Templates {
+	pub async fn load(s3_client: &Client, maven_config: &MavenConfig) -> Templates {
+		let mut environment = Environment::new();
+		// everything we render is html, even if an override in the bucket is named something else
+		environment.set_auto_escape_callback(|_| { AutoEscape::Html });
+		// poms can put anything in a url, only plain web links are safe to put in an href
+		environment.add_test("web_url", |value: Option<&str>| {
+			value.is_some_and(|it| { it.starts_with("https://") || it.starts_with("http://") })
+		});
+
+		for (name, default_source) in DEFAULT_TEMPLATES {
+			let key = format!("{TEMPLATE_PREFIX}{name}");
+			let source = match storage::get_resource(s3_client, maven_config, &key).await {
+				None => { String::from(default_source) }
+				Some(obj) => {
+					match obj.body.collect().await {
+						Ok(bytes) => {
+							tracing::info!("Using template \"{key}\" from the bucket");
+							String::from_utf8_lossy(&bytes.into_bytes()).into_owned()
+						}
+						Err(_) => { String::from(default_source) }
+					}
+				}
+			};
+
+			// a broken override shouldn't take the whole index down with it
+			if let Err(err) = environment.add_template_owned(name, source) {
+				tracing::error!("Template \"{key}\" is invalid, falling back to the default. {err}");
+				environment.add_template(name, default_source)
+					.expect("Built in templates must be valid");
+			}
 		}
-		builder.append("		</ul>\n");
+
+		environment.add_global("brand", Value::from_serialize(&maven_config.branding));
+		Templates { environment }
+	}
+
+	fn render(&self, name: &str, context: Value) -> Result<String, Error> {
+		Ok(self.environment.get_template(name)?.render(context)?)
 	}
+}
 
-	if !layer.files.is_empty() {
-		builder.append("		<h3>Files:</h3>\n");
-		builder.append("		<ul>\n");
-		for file in &layer.files {
-			builder.append(format!("			<li><a href=\"./{file}\" download>{file}</a></li>\n"))
+// Names come straight from s3 keys, hrefs are url encoded here and templates escape everything on top of that
+#[derive(Serialize)]
+struct Crumb<'a> {
+	name: &'a str,
+	href: String
+}
+
+#[derive(Serialize)]
+struct PackageRow<'a> {
+	name: &'a str,
+	href: String,
+	modified: String
+}
+
+#[derive(Serialize)]
+struct FileRow<'a> {
+	name: &'a str,
+	href: String,
+	modified: String,
+	size: String
+}
+
+#[derive(Serialize)]
+struct SearchRow<'a> {
+	group_id: &'a str,
+	artifact_id: &'a str,
+	version: &'a str,
+	href: String,
+	modified: String
+}
+
+#[derive(Serialize)]
+struct DependencyRow<'a> {
+	group_id: &'a str,
+	artifact_id: &'a str,
+	version: Option<&'a str>,
+	scope: Option<&'a str>,
+	optional: bool,
+	href: Option<String>
+}
+
+pub fn build_index(templates: &Templates, req_slice: &Vec<&str>, layer: &Layer) -> Result<String, Error> {
+	templates.render("index.html", index_context(req_slice, layer))
+}
+
+// A version directory with a pom, the regular listing plus what the pom says about it
+pub fn build_version(templates: &Templates, req_slice: &Vec<&str>, layer: &Layer, pom: &Pom, index: &RepositoryIndex, snippets: &[Snippet]) -> Result<String, Error> {
+	let root_href = up(req_slice.len());
+	let dependencies: Vec<DependencyRow> = pom.dependencies.iter().map(|it| {
+		// link the exact version if we have it, otherwise at least the artifact
+		let artifact_directory = format!("{}/{}", it.group_id.replace('.', "/"), it.artifact_id);
+		let version_directory = it.version.as_ref().map(|version| { format!("{artifact_directory}/{version}") });
+		let href = version_directory.into_iter().chain([artifact_directory])
+			.find(|directory| { index.contains_directory(directory) })
+			.map(|directory| { format!("{root_href}{}/", encode_path(&directory)) });
+
+		DependencyRow {
+			group_id: &it.group_id,
+			artifact_id: &it.artifact_id,
+			version: it.version.as_deref(),
+			scope: it.scope.as_deref(),
+			optional: it.optional,
+			href
 		}
-		builder.append("		</ul>\n");
+	}).collect();
+
+	templates.render("version.html", context! {
+		pom,
+		dependencies,
+		snippets,
+		..index_context(req_slice, layer)
+	})
+}
+
+// each part of the path links back up to that directory
+fn up(levels: usize) -> String {
+	if levels == 0 { String::from("./") } else { "../".repeat(levels) }
+}
+
+fn index_context(req_slice: &Vec<&str>, layer: &Layer) -> Value {
+	let breadcrumbs: Vec<Crumb> = req_slice.iter().enumerate().map(|(index, part)| {
+		Crumb {
+			name: part,
+			href: up(req_slice.len() - index - 1)
+		}
+	}).collect();
+
+	let packages: Vec<PackageRow> = layer.packages.iter().map(|it| {
+		PackageRow {
+			name: &it.name,
+			href: format!("./{}/", encode_path_segment(&it.name)),
+			modified: it.last_modified.map(format_date).unwrap_or_default()
+		}
+	}).collect();
+
+	let files: Vec<FileRow> = layer.files.iter().map(|it| {
+		FileRow {
+			name: &it.name,
+			href: format!("./{}", encode_path_segment(&it.name)),
+			modified: format_date(it.last_modified),
+			size: format_size(it.size)
+		}
+	}).collect();
+
+	context! {
+		path => req_slice.join("/") + "/",
+		root_href => up(req_slice.len()),
+		breadcrumbs,
+		packages,
+		files
 	}
+}
 
-	builder.append("	  </body>\n");
-	builder.append("</html>\n");
-	builder.string().unwrap()
-}
\ No newline at end of file
+pub fn build_search(templates: &Templates, query: &SearchQuery, results: &[SearchResult]) -> Result<String, Error> {
+	let rows: Vec<SearchRow> = results.iter().map(|it| {
+		SearchRow {
+			group_id: &it.group_id,
+			artifact_id: &it.artifact_id,
+			version: &it.version,
+			href: format!("./{}", encode_path(&it.path)),
+			modified: format_date(it.last_modified)
+		}
+	}).collect();
+
+	templates.render("search.html", context! {
+		query => context! {
+			text => query.text.as_deref().unwrap_or_default(),
+			group_id => query.group_id.as_deref().unwrap_or_default(),
+			artifact_id => query.artifact_id.as_deref().unwrap_or_default(),
+			version => query.version.as_deref().unwrap_or_default()
+		},
+		searched => !query.is_empty(),
+		results => rows
+	})
+}
+
+// "2023-10-12 12:00", nobody browsing a maven needs the seconds
+fn format_date(epoch_seconds: i64) -> String {
+	let formatted = DateTime::from_secs(epoch_seconds)
+		.fmt(DateTimeFormat::DateTime)
+		.unwrap_or_default();
+	formatted.replace('T', " ").chars().take(16).collect()
+}
+
+fn format_size(bytes: i64) -> String {
+	const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+	if bytes < 1024 {
+		return format!("{bytes} B")
+	}
+
+	let mut size = bytes as f64 / 1024.0;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	format!("{size:.1} {}", UNITS[unit])
+}
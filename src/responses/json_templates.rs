@@ -0,0 +1,135 @@
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use serde::Serialize;
+use crate::auth::rules::Grant;
+use crate::auth::tokens::StoredToken;
+use crate::search::SearchResult;
+use crate::storage::audit::AuditEvent;
+use crate::storage::layers::Layer;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexListing<'a> {
+	path: String,
+	packages: Vec<PackageListing<'a>>,
+	files: Vec<FileListing<'a>>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageListing<'a> {
+	name: &'a str,
+	last_modified: Option<String>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileListing<'a> {
+	name: &'a str,
+	size: i64,
+	last_modified: String
+}
+
+// Same content as the html index, for scripts that would rather not scrape it
+pub fn build_index(req_slice: &Vec<&str>, layer: &Layer) -> String {
+	let listing = IndexListing {
+		path: req_slice.join("/") + "/",
+		packages: layer.packages.iter().map(|it| {
+			PackageListing {
+				name: &it.name,
+				last_modified: it.last_modified.map(format_time)
+			}
+		}).collect(),
+		files: layer.files.iter().map(|it| {
+			FileListing {
+				name: &it.name,
+				size: it.size,
+				last_modified: format_time(it.last_modified)
+			}
+		}).collect()
+	};
+
+	serde_json::to_string(&listing).expect("Failed to serialize index listing")
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchListing<'a> {
+	group_id: &'a str,
+	artifact_id: &'a str,
+	version: &'a str,
+	path: &'a str,
+	last_modified: String
+}
+
+pub fn build_search(results: &[SearchResult]) -> String {
+	let listing: Vec<SearchListing> = results.iter().map(|it| {
+		SearchListing {
+			group_id: &it.group_id,
+			artifact_id: &it.artifact_id,
+			version: &it.version,
+			path: &it.path,
+			last_modified: format_time(it.last_modified)
+		}
+	}).collect();
+
+	serde_json::to_string(&listing).expect("Failed to serialize search results")
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LatestListing<'a> {
+	group_id: &'a str,
+	artifact_id: &'a str,
+	version: &'a str
+}
+
+pub fn build_latest(group_id: &str, artifact_id: &str, version: &str) -> String {
+	let listing = LatestListing { group_id, artifact_id, version };
+	serde_json::to_string(&listing).expect("Failed to serialize latest version")
+}
+
+// Everything about a token except its hash, the secret itself only ever goes out once when it's created
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenListing<'a> {
+	id: &'a str,
+	name: &'a str,
+	created: String,
+	expires: String,
+	expired: bool,
+	grants: &'a Option<Vec<Grant>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	token: Option<&'a str>
+}
+
+fn token_listing<'a>(stored: &'a StoredToken, token: Option<&'a str>) -> TokenListing<'a> {
+	TokenListing {
+		id: &stored.id,
+		name: &stored.name,
+		created: format_time(stored.created),
+		expires: format_time(stored.expires),
+		expired: stored.is_expired(),
+		grants: &stored.grants,
+		token
+	}
+}
+
+pub fn build_tokens(tokens: &[StoredToken]) -> String {
+	let listing: Vec<TokenListing> = tokens.iter().map(|it| { token_listing(it, None) }).collect();
+	serde_json::to_string(&listing).expect("Failed to serialize tokens")
+}
+
+pub fn build_created_token(stored: &StoredToken, token: &str) -> String {
+	serde_json::to_string(&token_listing(stored, Some(token))).expect("Failed to serialize token")
+}
+
+pub fn build_audit(events: &[AuditEvent]) -> String {
+	serde_json::to_string(events).expect("Failed to serialize audit events")
+}
+
+// RFC 3339, which everything that might read this understands
+fn format_time(epoch_seconds: i64) -> String {
+	DateTime::from_secs(epoch_seconds)
+		.fmt(DateTimeFormat::DateTime)
+		.expect("Failed to format timestamp")
+}
@@ -0,0 +1,54 @@
+use serde::Serialize;
+use crate::cfg::MavenConfig;
+use crate::maven::pom::Pom;
+
+// What to paste into a build file to use a version, the repository block is what people kept getting wrong
+#[derive(Serialize)]
+pub struct Snippet {
+	pub name: &'static str,
+	pub repository: String,
+	pub dependency: String
+}
+
+pub fn build_snippets(maven_config: &MavenConfig, pom: &Pom, base_url: &str) -> Vec<Snippet> {
+	let id = &maven_config.indexer_repository_id;
+	let (group_id, artifact_id, version) = (&pom.group_id, &pom.artifact_id, &pom.version);
+
+	// these packagings all resolve to a plain jar, anything else has to be asked for by type
+	let artifact_type = match pom.packaging.as_str() {
+		"jar" | "bundle" | "maven-plugin" | "eclipse-plugin" => { None }
+		packaging => { Some(packaging) }
+	};
+	let maven_type = artifact_type.map(|it| { format!("\n    <type>{it}</type>") }).unwrap_or_default();
+	let gradle_type = artifact_type.map(|it| { format!("@{it}") }).unwrap_or_default();
+	let sbt_type = artifact_type.map(|it| { format!(" artifacts(Artifact(\"{artifact_id}\", \"{it}\", \"{it}\"))") }).unwrap_or_default();
+	let leiningen_type = artifact_type.map(|it| { format!(" :extension \"{it}\"") }).unwrap_or_default();
+
+	vec![
+		Snippet {
+			name: "Maven",
+			repository: format!("<repository>\n    <id>{id}</id>\n    <url>{base_url}</url>\n</repository>"),
+			dependency: format!("<dependency>\n    <groupId>{group_id}</groupId>\n    <artifactId>{artifact_id}</artifactId>\n    <version>{version}</version>{maven_type}\n</dependency>")
+		},
+		Snippet {
+			name: "Gradle (Kotlin)",
+			repository: format!("repositories {{\n    maven(\"{base_url}\")\n}}"),
+			dependency: format!("implementation(\"{group_id}:{artifact_id}:{version}{gradle_type}\")")
+		},
+		Snippet {
+			name: "Gradle (Groovy)",
+			repository: format!("repositories {{\n    maven {{ url '{base_url}' }}\n}}"),
+			dependency: format!("implementation '{group_id}:{artifact_id}:{version}{gradle_type}'")
+		},
+		Snippet {
+			name: "sbt",
+			repository: format!("resolvers += \"{id}\" at \"{base_url}\""),
+			dependency: format!("libraryDependencies += \"{group_id}\" % \"{artifact_id}\" % \"{version}\"{sbt_type}")
+		},
+		Snippet {
+			name: "Leiningen",
+			repository: format!(":repositories [[\"{id}\" \"{base_url}\"]]"),
+			dependency: format!("[{group_id}/{artifact_id} \"{version}\"{leiningen_type}]")
+		}
+	]
+}
@@ -0,0 +1,61 @@
+use string_builder::Builder;
+use crate::badge::BadgeStyle;
+use crate::util::escape_xml;
+
+// Rough Verdana 11px advance widths, we cant measure text in here and close enough looks fine
+fn text_width(text: &str) -> u32 {
+	text.chars().map(|it| {
+		match it {
+			'i' | 'l' | 'j' | '.' | ',' | ':' | ';' | '|' | '!' | '\'' | ' ' => { 4 }
+			'f' | 't' | 'r' | 'I' | '-' | '(' | ')' | '[' | ']' => { 5 }
+			'm' | 'w' | 'M' | 'W' => { 10 }
+			'A'..='Z' => { 8 }
+			_ => { 7 }
+		}
+	}).sum()
+}
+
+// A two part shields style badge, "label | value"
+pub fn build_badge(label: &str, value: &str, color: &str, style: BadgeStyle) -> String {
+	let (label, value, height, font_size, padding, text_y) = match style {
+		BadgeStyle::ForTheBadge => { (label.to_uppercase(), value.to_uppercase(), 28, 10, 24, 18) }
+		_ => { (String::from(label), String::from(value), 20, 11, 10, 14) }
+	};
+
+	// for-the-badge is letter spaced, which adds up for long labels
+	let spacing = |text: &str| { if matches!(style, BadgeStyle::ForTheBadge) { text.chars().count() as u32 } else { 0 } };
+	let label_width = text_width(&label) + spacing(&label) + padding;
+	let value_width = text_width(&value) + spacing(&value) + padding;
+	let width = label_width + value_width;
+	let radius = if matches!(style, BadgeStyle::Flat) { 3 } else { 0 };
+
+	let label = escape_xml(&label);
+	let value = escape_xml(&value);
+	let label_x = label_width / 2;
+	let value_x = label_width + value_width / 2;
+	let font_weight = if matches!(style, BadgeStyle::ForTheBadge) { " font-weight=\"bold\" letter-spacing=\"1\"" } else { "" };
+
+	let mut builder = Builder::new(1024);
+	builder.append(format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" role=\"img\" aria-label=\"{label}: {value}\">"));
+	builder.append(format!("<title>{label}: {value}</title>"));
+	if matches!(style, BadgeStyle::Flat) {
+		builder.append("<linearGradient id=\"s\" x2=\"0\" y2=\"100%\"><stop offset=\"0\" stop-color=\"#bbb\" stop-opacity=\".1\"/><stop offset=\"1\" stop-opacity=\".1\"/></linearGradient>");
+	}
+	builder.append(format!("<clipPath id=\"r\"><rect width=\"{width}\" height=\"{height}\" rx=\"{radius}\" fill=\"#fff\"/></clipPath>"));
+	builder.append("<g clip-path=\"url(#r)\">");
+	builder.append(format!("<rect width=\"{label_width}\" height=\"{height}\" fill=\"#555\"/>"));
+	builder.append(format!("<rect x=\"{label_width}\" width=\"{value_width}\" height=\"{height}\" fill=\"{color}\"/>"));
+	if matches!(style, BadgeStyle::Flat) {
+		builder.append(format!("<rect width=\"{width}\" height=\"{height}\" fill=\"url(#s)\"/>"));
+	}
+	builder.append("</g>");
+	builder.append(format!("<g fill=\"#fff\" text-anchor=\"middle\" font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" font-size=\"{font_size}\"{font_weight}>"));
+	if matches!(style, BadgeStyle::Flat) {
+		builder.append(format!("<text x=\"{label_x}\" y=\"{}\" fill=\"#010101\" fill-opacity=\".3\">{label}</text>", text_y + 1));
+		builder.append(format!("<text x=\"{value_x}\" y=\"{}\" fill=\"#010101\" fill-opacity=\".3\">{value}</text>", text_y + 1));
+	}
+	builder.append(format!("<text x=\"{label_x}\" y=\"{text_y}\">{label}</text>"));
+	builder.append(format!("<text x=\"{value_x}\" y=\"{text_y}\">{value}</text>"));
+	builder.append("</g></svg>\n");
+	builder.string().unwrap()
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use lambda_http::Request;
+use crate::auth::ReadAccess;
+use crate::maven::layout;
+use crate::maven::layout::MavenPath;
+use crate::maven::version::compare_versions;
+use crate::storage::repository_index::RepositoryIndex;
+use crate::util::query_param;
+
+// Stop a search for "a" from returning the whole repository
+pub const MAX_RESULTS: usize = 100;
+
+// Each coordinate matches exactly, or as a prefix if it ends with '*'
+// Free text has to match every whitespace separated word somewhere in "group:artifact:version"
+pub struct SearchQuery {
+	pub group_id: Option<String>,
+	pub artifact_id: Option<String>,
+	pub version: Option<String>,
+	pub text: Option<String>
+}
+
+pub struct SearchResult {
+	pub group_id: String,
+	pub artifact_id: String,
+	pub version: String,
+	pub path: String,
+	pub last_modified: i64
+}
+
+impl SearchQuery {
+	pub fn from_request(request: &Request) -> SearchQuery {
+
+		SearchQuery {
+			group_id: query_param(request, "g"),
+			artifact_id: query_param(request, "a"),
+			version: query_param(request, "v"),
+			text: query_param(request, "q")
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.group_id.is_none() && self.artifact_id.is_none() && self.version.is_none() && self.text.is_none()
+	}
+
+	fn matches(&self, group_id: &str, artifact_id: &str, version: &str) -> bool {
+		if !matches_coordinate(&self.group_id, group_id)
+			|| !matches_coordinate(&self.artifact_id, artifact_id)
+			|| !matches_coordinate(&self.version, version) {
+			return false
+		}
+
+		match &self.text {
+			None => { true }
+			Some(text) => {
+				let haystack = format!("{group_id}:{artifact_id}:{version}").to_lowercase();
+				text.to_lowercase().split_whitespace().all(|word| { haystack.contains(word) })
+			}
+		}
+	}
+}
+
+fn matches_coordinate(pattern: &Option<String>, value: &str) -> bool {
+	match pattern {
+		None => { true }
+		Some(pattern) => {
+			match pattern.strip_suffix('*') {
+				None => { pattern == value }
+				Some(prefix) => { value.starts_with(prefix) }
+			}
+		}
+	}
+}
+
+// Walks the persisted index for version directories, "com/example/lib/1.0/lib-1.0.jar" is com.example:lib:1.0
+pub fn search(index: &RepositoryIndex, query: &SearchQuery, read_access: &ReadAccess) -> Vec<SearchResult> {
+	let mut found: BTreeMap<(String, String, String), SearchResult> = BTreeMap::new();
+
+	for (key, file) in &index.files {
+		let artifact = match layout::parse(key) {
+			Some(MavenPath::Artifact(artifact)) if artifact.checksum.is_none() => { artifact }
+			_ => { continue }
+		};
+
+		if !query.matches(&artifact.group_id, &artifact.artifact_id, &artifact.version) || !read_access.can_read(key) {
+			continue
+		}
+
+		let result = found.entry((artifact.group_id.clone(), artifact.artifact_id.clone(), artifact.version.clone()))
+			.or_insert_with(|| {
+				SearchResult {
+					path: format!("{}/", artifact.version_directory()),
+					group_id: artifact.group_id,
+					artifact_id: artifact.artifact_id,
+					version: artifact.version,
+					last_modified: 0
+				}
+			});
+		result.last_modified = result.last_modified.max(file.last_modified);
+	}
+
+	// newest versions of each artifact first
+	let mut results: Vec<SearchResult> = found.into_values().collect();
+	results.sort_by(|a, b| {
+		a.group_id.cmp(&b.group_id)
+			.then_with(|| { a.artifact_id.cmp(&b.artifact_id) })
+			.then_with(|| { compare_versions(&b.version, &a.version) })
+	});
+	results.truncate(MAX_RESULTS);
+	results
+}
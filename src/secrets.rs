@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use aws_config::SdkConfig;
+use aws_sdk_secretsmanager::error::DisplayErrorContext;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+// Environment variables like "secret://maven/upload#password" or "ssm:///maven/upload-password" are resolved before
+// the config reads them, with the same credentials and region as every other client
+const SECRET_SCHEME: &str = "secret://";
+const PARAMETER_SCHEME: &str = "ssm://";
+
+// What each reference stood for, or why it couldn't be fetched. Filled once when the instance starts
+static RESOLVED: Lazy<Mutex<HashMap<String, Result<String, String>>>> = Lazy::new(|| { Mutex::new(HashMap::new()) });
+
+// Secrets Manager for secret://, the parameter store for ssm://
+pub struct SecretClients {
+	secrets: aws_sdk_secretsmanager::Client,
+	parameters: aws_sdk_ssm::Client
+}
+
+impl SecretClients {
+	pub fn new(sdk_config: &SdkConfig) -> SecretClients {
+		SecretClients {
+			secrets: aws_sdk_secretsmanager::Client::new(sdk_config),
+			parameters: aws_sdk_ssm::Client::new(sdk_config)
+		}
+	}
+}
+
+pub fn is_reference(value: &str) -> bool {
+	value.starts_with(SECRET_SCHEME) || value.starts_with(PARAMETER_SCHEME)
+}
+
+// Fetches every reference that wasn't fetched yet, failures are kept for resolve to report
+pub async fn load(sdk_config: &SdkConfig, references: impl Iterator<Item = String>) {
+	let references: Vec<String> = references
+		.filter(|it| { !RESOLVED.lock().unwrap().contains_key(it) })
+		.collect();
+	if references.is_empty() {
+		return
+	}
+
+	let clients = SecretClients::new(sdk_config);
+	for reference in &references {
+		let value = fetch(&clients, reference).await;
+		RESOLVED.lock().unwrap().insert(reference.clone(), value);
+	}
+	tracing::info!("Resolved {} secrets", references.len());
+}
+
+// The value a reference stands for, anything else is returned as it is
+pub fn resolve(value: &str) -> Result<String, String> {
+	if !is_reference(value) {
+		return Ok(String::from(value))
+	}
+	match RESOLVED.lock().unwrap().get(value) {
+		None => { Err(format!("\"{value}\" wasn't loaded before it was needed.")) }
+		Some(resolved) => { resolved.clone() }
+	}
+}
+
+// "secret://name#key" picks one key out of a secret stored as json, like the ones rds creates
+async fn fetch(clients: &SecretClients, reference: &str) -> Result<String, String> {
+	let (reference, key) = match reference.split_once('#') {
+		None => { (reference, None) }
+		Some((reference, key)) => { (reference, Some(key)) }
+	};
+
+	let value = if let Some(name) = reference.strip_prefix(SECRET_SCHEME) {
+		let output = clients.secrets.get_secret_value()
+			.secret_id(name)
+			.send().await
+			.map_err(|err| { format!("Failed to read \"{reference}\". {}", DisplayErrorContext(&err)) })?;
+		output.secret_string
+			.ok_or_else(|| { format!("\"{reference}\" has no text value, binary secrets aren't supported.") })?
+	} else if let Some(name) = reference.strip_prefix(PARAMETER_SCHEME) {
+		let output = clients.parameters.get_parameter()
+			.name(name)
+			.with_decryption(true)
+			.send().await
+			.map_err(|err| { format!("Failed to read \"{reference}\". {}", DisplayErrorContext(&err)) })?;
+		output.parameter.and_then(|it| { it.value })
+			.ok_or_else(|| { format!("\"{reference}\" has no value.") })?
+	} else {
+		return Err(format!("\"{reference}\" is not a secret:// or ssm:// reference."))
+	};
+
+	match key {
+		None => { Ok(value) }
+		Some(key) => {
+			// the error could quote the secret, so it's left out
+			let fields: Value = serde_json::from_str(&value).map_err(|_| { format!("\"{reference}\" is not json, so it has no key \"{key}\".") })?;
+			fields[key].as_str()
+				.map(String::from)
+				.ok_or_else(|| { format!("\"{reference}\" has no text key \"{key}\".") })
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{BufRead, BufReader, Read, Write};
+	use std::net::TcpListener;
+	use aws_sdk_secretsmanager::config::{Credentials, Region};
+	use super::*;
+
+	// Answers every request with the same status and json body, standing in for both services
+	fn stand_in(status: u16, body: &'static str) -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		std::thread::spawn(move || {
+			for stream in listener.incoming() {
+				let mut reader = BufReader::new(stream.unwrap());
+				let mut length = 0;
+				loop {
+					let mut line = String::new();
+					reader.read_line(&mut line).unwrap();
+					if line.trim().is_empty() {
+						break
+					}
+					if let Some((name, value)) = line.split_once(':') {
+						if name.eq_ignore_ascii_case("content-length") {
+							length = value.trim().parse().unwrap();
+						}
+					}
+				}
+				let mut request = vec![0; length];
+				reader.read_exact(&mut request).unwrap();
+
+				let response = format!(
+					"HTTP/1.1 {status} Stand-in\r\nContent-Type: application/x-amz-json-1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+					body.len()
+				);
+				reader.get_mut().write_all(response.as_bytes()).unwrap();
+			}
+		});
+		url
+	}
+
+	fn clients(url: &str) -> SecretClients {
+		let credentials = Credentials::new("test", "test", None, None, "test");
+		SecretClients {
+			secrets: aws_sdk_secretsmanager::Client::from_conf(aws_sdk_secretsmanager::Config::builder()
+				.region(Region::new("us-east-1"))
+				.credentials_provider(credentials.clone())
+				.endpoint_url(url)
+				.build()),
+			parameters: aws_sdk_ssm::Client::from_conf(aws_sdk_ssm::Config::builder()
+				.region(Region::new("us-east-1"))
+				.credentials_provider(credentials)
+				.endpoint_url(url)
+				.build())
+		}
+	}
+
+	#[tokio::test]
+	async fn fetches_a_plain_secret() {
+		let clients = clients(&stand_in(200, r#"{"Name": "maven/upload", "SecretString": "hunter2"}"#));
+		assert_eq!(fetch(&clients, "secret://maven/upload").await, Ok(String::from("hunter2")));
+	}
+
+	#[tokio::test]
+	async fn fetches_a_plain_parameter() {
+		let clients = clients(&stand_in(200, r#"{"Parameter": {"Name": "/maven/upload-password", "Type": "SecureString", "Value": "hunter2"}}"#));
+		assert_eq!(fetch(&clients, "ssm:///maven/upload-password").await, Ok(String::from("hunter2")));
+	}
+
+	#[tokio::test]
+	async fn picks_a_key_out_of_a_json_secret() {
+		let clients = clients(&stand_in(200, r#"{"SecretString": "{\"username\": \"ci\", \"password\": \"hunter2\"}"}"#));
+		assert_eq!(fetch(&clients, "secret://maven/upload#password").await, Ok(String::from("hunter2")));
+		assert!(fetch(&clients, "secret://maven/upload#token").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn refuses_a_key_of_a_plain_secret() {
+		let clients = clients(&stand_in(200, r#"{"SecretString": "hunter2"}"#));
+		let err = fetch(&clients, "secret://maven/upload#password").await.unwrap_err();
+		assert!(err.contains("is not json"));
+		assert!(!err.contains("hunter2"));
+	}
+
+	#[tokio::test]
+	async fn reports_errors_from_the_service() {
+		let clients = clients(&stand_in(400, r#"{"__type": "ResourceNotFoundException", "message": "Secrets Manager can't find the specified secret."}"#));
+		let err = fetch(&clients, "secret://maven/missing").await.unwrap_err();
+		assert!(err.contains("ResourceNotFoundException"), "{err}");
+	}
+}
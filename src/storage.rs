@@ -1,4 +1,9 @@
+pub mod audit;
+pub mod checksums;
 pub mod layers;
+pub mod metadata;
+pub mod nexus_index;
+pub mod repository_index;
 
 use std::sync::{Arc, Mutex};
 use aws_sdk_s3::Client;
@@ -9,32 +14,106 @@ use http::Response;
 use lambda_http::Body;
 use lambda_runtime::Error;
 use crate::cfg::MavenConfig;
+use crate::maven::pom::Pom;
 use crate::responses::build_response::{ErrorResponseBuilder, ResponseBuilder};
-use crate::storage::layers::Layer;
-use crate::util::is_file_request;
+use crate::storage::layers::{Layer, LayerFile};
+
+// Everything we generate for ourselves lives under here and is never served or indexed
+pub const INTERNAL_PREFIX: &str = ".maven/";
+
+pub fn is_internal(key: &str) -> bool {
+	key.starts_with(INTERNAL_PREFIX)
+}
+
+// Lists every object under a prefix, following continuation tokens past the 1k limit of a single request
+pub async fn list_objects(s3_client: &Client, maven_config: &MavenConfig, prefix: &str) -> Result<Vec<Object>, Error> {
+	let mut objects = Vec::new();
+	let mut continuation: Option<String> = None;
+	loop {
+		let list = s3_client.list_objects_v2()
+			.bucket(&maven_config.bucket_name)
+			.prefix(prefix)
+			.set_continuation_token(continuation)
+			.send().await?;
+
+		objects.extend(list.contents.unwrap_or_default());
+		continuation = list.next_continuation_token;
+		if continuation.is_none() {
+			return Ok(objects)
+		}
+	}
+}
+
+// Lists only the objects directly under a prefix like "com/example/lib/1.0/", nothing from the directories below it
+pub async fn list_files(s3_client: &Client, maven_config: &MavenConfig, prefix: &str) -> Result<Vec<Object>, Error> {
+	let mut objects = Vec::new();
+	let mut continuation: Option<String> = None;
+	loop {
+		let list = s3_client.list_objects_v2()
+			.bucket(&maven_config.bucket_name)
+			.prefix(prefix)
+			.delimiter('/')
+			.set_continuation_token(continuation)
+			.send().await?;
+
+		objects.extend(list.contents.unwrap_or_default());
+		continuation = list.next_continuation_token;
+		if continuation.is_none() {
+			return Ok(objects)
+		}
+	}
+}
 
 // Requests a file from s3 and returns it
-pub async fn get_resource<'a>(s3_client: &Client, maven_config: MavenConfig, request_path: &String) -> Option<GetObjectOutput> {
+pub async fn get_resource(s3_client: &Client, maven_config: &MavenConfig, request_path: &str) -> Option<GetObjectOutput> {
 	tracing::info!("Getting object \"{request_path}\"");
 	let obj = s3_client.get_object()
-		.bucket(maven_config.bucket_name)
+		.bucket(&maven_config.bucket_name)
 		.key(request_path)
 		.send().await;
 
-	match obj {
-		Err(..) => {
-			None
-		}
+	obj.ok()
+}
 
-		Ok(result) => {
-			Some(result)
-		}
+// The whole object as text, for the config kept in the bucket. Takes the bucket on its own since it's needed before the config is
+pub async fn get_object_text(s3_client: &Client, bucket_name: &str, key: &str) -> Option<String> {
+	tracing::info!("Getting object \"{key}\"");
+	let obj = s3_client.get_object()
+		.bucket(bucket_name)
+		.key(key)
+		.send().await.ok()?;
+	let bytes = obj.body.collect().await.ok()?.into_bytes();
+	Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// The pom of a version directory like "com/example/lib/1.0", if the layer of it has one
+// Snapshot directories only have timestamped poms, the newest one describes the snapshot
+pub async fn get_pom(s3_client: &Client, maven_config: &MavenConfig, req_slice: &[&str], layer: &Layer) -> Option<Pom> {
+	let (version, artifact_id) = match req_slice {
+		[.., _, artifact_id, version] => { (*version, *artifact_id) }
+		_ => { return None }
+	};
+
+	let exact = format!("{artifact_id}-{version}.pom");
+	let file = layer.files.iter().find(|it| { it.name == exact }).or_else(|| {
+		let base = format!("{artifact_id}-{}-", version.strip_suffix("-SNAPSHOT")?);
+		layer.files.iter()
+			.filter(|it| { it.name.starts_with(&base) && it.name.ends_with(".pom") })
+			.max_by_key(|it| { it.last_modified })
+	})?;
+
+	let key = format!("{}/{}", req_slice.join("/"), file.name);
+	let bytes = get_resource(s3_client, maven_config, &key).await?.body.collect().await.ok()?.into_bytes();
+	let pom = Pom::parse(&String::from_utf8_lossy(&bytes));
+	if pom.is_none() {
+		tracing::warn!("\"{key}\" is not a valid pom, showing a plain index instead");
 	}
+	pom
 }
 
 // Builds an index using the "prefix" property of s3 indexing queries
 // Technically this could break if we have >1k entries under a prefix but that seems unlikely!
-pub async fn get_index<'a>(s3_client: &Client, maven_config: MavenConfig, root_layer_holder: &Arc<Mutex<Layer>>, request_path: &str) -> Option<Layer> {
+pub async fn get_index(s3_client: &Client, maven_config: &MavenConfig, root_layer_holder: &Arc<Mutex<Layer>>, request_path: &str) -> Option<Layer> {
 	let path_prefix = request_path.rsplit_once('/').unwrap_or(("", "")).0;
 	let request_split: Vec<&str> = request_path.split('/').filter(|it| { !it.is_empty() }).collect();
 
@@ -51,7 +130,7 @@ pub async fn get_index<'a>(s3_client: &Client, maven_config: MavenConfig, root_l
 
 	if path_prefix.is_empty() {
 		let list = s3_client.list_objects_v2()
-			.bucket(maven_config.bucket_name)
+			.bucket(&maven_config.bucket_name)
 			.max_keys(maven_config.indexing_max_keys)
 			.delimiter('/')
 			.send().await
@@ -67,22 +146,22 @@ pub async fn get_index<'a>(s3_client: &Client, maven_config: MavenConfig, root_l
 		let mut root_layer = root_layer_holder.lock().unwrap();
 		for obj in prefixes {
 			let key = obj.prefix.unwrap();
+			if is_internal(&key) {
+				continue
+			}
 			let splice: Vec<&str> = key.split('/').filter(|it| { !it.is_empty() }).collect();
-			root_layer.populate(&splice, 0);
+			root_layer.populate(&splice, 0, None);
 		}
 		drop(root_layer)
 	} else {
 		let list = s3_client.list_objects_v2()
-			.bucket(maven_config.bucket_name)
+			.bucket(&maven_config.bucket_name)
 			.max_keys(maven_config.indexing_max_keys)
 			.prefix(path_prefix)
 			.send().await
 			.expect("Failed to get bucket contents, did you setup the permissions properly?");
 
-		let content: Vec<Object> = match list.contents {
-			None => {Vec::new()}
-			Some(bucket_content) => { bucket_content }
-		};
+		let content: Vec<Object> = list.contents.unwrap_or_default();
 
 		if content.is_empty() {
 			tracing::info!("Found no content");
@@ -92,31 +171,64 @@ pub async fn get_index<'a>(s3_client: &Client, maven_config: MavenConfig, root_l
 		let mut root_layer = root_layer_holder.lock().unwrap();
 		for obj in content {
 			let key = obj.key.unwrap();
+			let last_modified = obj.last_modified.map(|it| { it.secs() }).unwrap_or(0);
 			let mut splice: Vec<&str> = key.split('/').filter(|it| { !it.is_empty() }).collect();
 			let last = splice.remove(splice.len() - 1);
-			let layer = root_layer.populate(&splice, 0);
-
-			if is_file_request(last) {
-				layer.files.push(String::from(last));
-				layer.files.dedup()
+			let layer = root_layer.populate(&splice, 0, Some(last_modified));
+
+			if !key.ends_with('/') {
+				layer.add_file(LayerFile {
+					name: String::from(last),
+					size: obj.size,
+					last_modified
+				})
 			}
 		}
 		drop(root_layer)
 	}
 
 	let root_layer = root_layer_holder.lock().unwrap();
-	return if root_layer.has_children(&request_split, 0) {
+	if root_layer.has_children(&request_split, 0) {
 		Some(root_layer.descend(&request_split, 0).clone())
 	} else {
 		None
 	}
 }
 
+// Removes a single file from s3, 404 if it was never there
+// Checksums and metadata are left for the s3 event of the delete to clean up, the same as with uploads
+pub async fn delete_artifact(s3_client: &Client, maven_config: &MavenConfig, key: &str) -> Result<Response<Body>, Error> {
+	// s3 happily "deletes" keys that never existed, but a client should know it got the path wrong
+	let exists = s3_client.head_object()
+		.bucket(&maven_config.bucket_name)
+		.key(key)
+		.send().await;
+	if exists.is_err() {
+		return ErrorResponseBuilder::no_content()
+	}
+
+	let result = s3_client.delete_object()
+		.bucket(&maven_config.bucket_name)
+		.key(key)
+		.send().await;
+
+	match result {
+		Ok(_) => {
+			tracing::info!("Deleted {key}");
+			ResponseBuilder::deleted_artifact()
+		}
+		Err(err) => {
+			tracing::error!("Failed to delete {key}. {err}");
+			ErrorResponseBuilder::server_error("Failed to delete artifact. Contact the maven owner for details")
+		}
+	}
+}
+
 // Puts a file into s3
 // Index is essentially rebuilt on each request already so pushing it wouldn't help at this scale
-pub async fn upload_artifact(s3_client: &Client, maven_config: MavenConfig, key: &String, body: &Body) -> Result<Response<Body>, Error> {
+pub async fn upload_artifact(s3_client: &Client, maven_config: &MavenConfig, key: &String, body: &Body) -> Result<Response<Body>, Error> {
 	let result = s3_client.put_object()
-		.bucket(maven_config.bucket_name)
+		.bucket(&maven_config.bucket_name)
 		.key(key)
 		.body(ByteStream::from(body.to_vec()))
 		.send().await;
@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{ByteStream, DateTime, DateTimeFormat};
+use data_encoding::HEXLOWER;
+use lambda_http::{Body, Request, RequestExt, Response};
+use lambda_http::request::RequestContext;
+use lambda_runtime::Error;
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::auth::Identity;
+use crate::auth::rules::Action;
+use crate::cfg::MavenConfig;
+use crate::storage;
+use crate::util::{now, query_param};
+
+// ".maven/audit/2024-05-01/13-1714568400000-1a2b3c4d.jsonl" holds what one instance saw in that hour, from the time of its
+// first event on. S3 can't append, so the instance rewrites its own object with every event, nobody else ever writes it
+// Nothing ever deletes them, a lifecycle rule on the prefix decides how long they are kept
+pub const AUDIT_PREFIX: &str = ".maven/audit/";
+
+// The object this instance is filling for the current hour, and everything in it so far
+struct Batch {
+	hour: String,
+	key: String,
+	lines: Vec<u8>
+}
+
+static BATCH: Lazy<Mutex<Option<Batch>>> = Lazy::new(|| { Mutex::new(None) });
+static INSTANCE: Lazy<u32> = Lazy::new(|| { OsRng.next_u32() });
+
+const DEFAULT_DAYS: i64 = 7;
+const MAX_DAYS: i64 = 90;
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+	Success,
+	// no credentials, or wrong ones
+	Unauthenticated,
+	// the right credentials for someone not allowed to do this
+	Forbidden,
+	Throttled,
+	// something wrong with the request itself, like the layout or size
+	Rejected,
+	Failed
+}
+
+impl Outcome {
+	fn from_status(status: u16) -> Outcome {
+		match status {
+			200..=299 => { Outcome::Success }
+			401 => { Outcome::Unauthenticated }
+			403 => { Outcome::Forbidden }
+			429 => { Outcome::Throttled }
+			400..=499 => { Outcome::Rejected }
+			_ => { Outcome::Failed }
+		}
+	}
+}
+
+// Who did what to which path, and how it went
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+	pub time: String,
+	pub action: Action,
+	pub path: String,
+	pub user: Option<String>,
+	pub token: Option<String>,
+	pub size: Option<usize>,
+	pub sha256: Option<String>,
+	pub source_ip: Option<String>,
+	pub user_agent: Option<String>,
+	pub status: u16,
+	pub outcome: Outcome
+}
+
+// GET api/audit?path=com/example/&user=alice&days=7&limit=100, newest first
+pub struct AuditQuery {
+	pub path: Option<String>,
+	pub user: Option<String>,
+	pub days: i64,
+	pub limit: usize
+}
+
+impl AuditQuery {
+	// None if days or limit aren't numbers in range
+	pub fn from_request(request: &Request) -> Option<AuditQuery> {
+
+		let days = match query_param(request, "days") {
+			None => { DEFAULT_DAYS }
+			Some(days) => { days.parse().ok().filter(|it| { (1..=MAX_DAYS).contains(it) })? }
+		};
+		let limit = match query_param(request, "limit") {
+			None => { DEFAULT_LIMIT }
+			Some(limit) => { limit.parse().ok().filter(|it| { (1..=MAX_LIMIT).contains(it) })? }
+		};
+
+		Some(AuditQuery {
+			// a leading slash is what people type, keys never have one
+			path: query_param(request, "path").map(|it| { String::from(it.trim_start_matches('/')) }),
+			user: query_param(request, "user"),
+			days,
+			limit
+		})
+	}
+
+	fn matches(&self, event: &AuditEvent) -> bool {
+		self.path.as_ref().is_none_or(|it| { event.path.starts_with(it.as_str()) })
+			&& self.user.as_ref().is_none_or(|it| { event.user.as_ref() == Some(it) })
+	}
+}
+
+// Writes down a PUT or DELETE once its response is known, allowed or not
+// A failure to write is logged but doesn't change the response, the change itself already happened
+// Attempts without valid credentials only go to the log, anyone could make those and they shouldn't cost us objects
+pub async fn record(
+	s3_client: &Client,
+	maven_config: &MavenConfig,
+	request: &Request,
+	action: Action,
+	path: &str,
+	identity: Option<&Identity>,
+	response: &Result<Response<Body>, Error>
+) {
+	let status = match response {
+		Ok(response) => { response.status().as_u16() }
+		Err(_) => { 500 }
+	};
+	let outcome = Outcome::from_status(status);
+	let source_ip = match request.request_context() {
+		RequestContext::ApiGatewayV2(context) => { context.http.source_ip }
+		_ => { None }
+	};
+
+	if matches!(outcome, Outcome::Unauthenticated | Outcome::Throttled) {
+		tracing::warn!("Refused {action} \"{path}\" from {} with {status}", source_ip.as_deref().unwrap_or("an unknown ip"));
+		return
+	}
+
+	// the size and hash of what was sent, so a replaced artifact can be told apart from the original
+	let body = if action == Action::Write { Some(request.body().as_ref()) } else { None };
+
+	let time = SystemTime::now();
+	let event = AuditEvent {
+		time: DateTime::from(time).fmt(DateTimeFormat::DateTime).expect("Failed to format timestamp"),
+		action,
+		path: String::from(path),
+		user: identity.map(|it| { it.name.clone() }),
+		token: identity.and_then(|it| { it.token.clone() }),
+		size: body.map(|it| { it.len() }),
+		sha256: body.map(|it| { HEXLOWER.encode(&Sha256::digest(it)) }),
+		source_ip,
+		user_agent: request.headers().get("User-Agent").and_then(|it| { it.to_str().ok() }).map(String::from),
+		status,
+		outcome
+	};
+
+	let mut line = serde_json::to_vec(&event).expect("Failed to serialize audit event");
+	line.push(b'\n');
+
+	// "2024-05-01T13", a new hour starts a new object
+	let hour = String::from(&event.time[..13]);
+	let (key, body) = {
+		let mut batch = BATCH.lock().unwrap();
+		if batch.as_ref().is_none_or(|it| { it.hour != hour }) {
+			let millis = time.duration_since(UNIX_EPOCH).map(|it| { it.as_millis() }).unwrap_or_default();
+			*batch = Some(Batch {
+				key: format!("{AUDIT_PREFIX}{}/{}-{millis:013}-{:08x}.jsonl", &hour[..10], &hour[11..], *INSTANCE),
+				hour,
+				lines: Vec::new()
+			});
+		}
+		let batch = batch.as_mut().expect("Batch was just started");
+		batch.lines.extend_from_slice(&line);
+		(batch.key.clone(), batch.lines.clone())
+	};
+
+	let result = s3_client.put_object()
+		.bucket(&maven_config.bucket_name)
+		.key(&key)
+		.content_type("application/x-ndjson")
+		.body(ByteStream::from(body))
+		.send().await;
+	if let Err(err) = result {
+		tracing::error!("Failed to write the audit event for {action} \"{path}\". {err}");
+	}
+}
+
+// Walks back an hour at a time from now, reading every instance's object of that hour until there are enough events
+pub async fn query(s3_client: &Client, maven_config: &MavenConfig, query: &AuditQuery) -> Result<Vec<AuditEvent>, Error> {
+	let today = now();
+	let mut events = Vec::new();
+
+	for day in 0..query.days {
+		let date = DateTime::from_secs(today - day * 24 * 60 * 60)
+			.fmt(DateTimeFormat::DateTime)
+			.expect("Failed to format timestamp");
+		let prefix = format!("{AUDIT_PREFIX}{}/", &date[..10]);
+
+		// keys start with the hour, so this groups the objects of each hour
+		let mut hours: BTreeMap<String, Vec<String>> = BTreeMap::new();
+		for obj in storage::list_objects(s3_client, maven_config, &prefix).await? {
+			let key = obj.key.unwrap_or_default();
+			let hour = key[prefix.len()..].split('-').next().map(String::from).unwrap_or_default();
+			hours.entry(hour).or_default().push(key);
+		}
+
+		for keys in hours.into_values().rev() {
+			let mut hour = Vec::new();
+			for key in keys {
+				let source = match storage::get_object_text(s3_client, &maven_config.bucket_name, &key).await {
+					None => { continue }
+					Some(source) => { source }
+				};
+				hour.extend(source.lines()
+					.filter_map(|it| { serde_json::from_str::<AuditEvent>(it).ok() })
+					.filter(|it| { query.matches(it) }));
+			}
+
+			// instances write side by side, so their events are merged back into one timeline
+			hour.sort_by_key(|it| { std::cmp::Reverse(DateTime::from_str(&it.time, DateTimeFormat::DateTime).ok()) });
+			events.extend(hour);
+			if events.len() >= query.limit {
+				events.truncate(query.limit);
+				return Ok(events)
+			}
+		}
+	}
+	Ok(events)
+}
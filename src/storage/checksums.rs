@@ -0,0 +1,98 @@
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::ByteStream;
+use data_encoding::HEXLOWER;
+use lambda_runtime::Error;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use crate::cfg::MavenConfig;
+use crate::storage;
+
+// Every checksum we keep next to an artifact, maven itself only really cares about sha1 and md5
+// but newer resolvers will happily use the sha2 variants if they exist
+pub const CHECKSUM_EXTENSIONS: [&str; 4] = ["md5", "sha1", "sha256", "sha512"];
+
+// Checks if a key is one of the checksum files we generate (or a client uploaded)
+pub fn is_checksum(key: &str) -> bool {
+	match key.rsplit_once('.') {
+		None => { false }
+		Some((_, extension)) => { CHECKSUM_EXTENSIONS.contains(&extension) }
+	}
+}
+
+// Hex encoded digest of the bytes for the given checksum extension
+pub fn digest(extension: &str, bytes: &[u8]) -> String {
+	match extension {
+		"md5" => { HEXLOWER.encode(&Md5::digest(bytes)) }
+		"sha1" => { HEXLOWER.encode(&Sha1::digest(bytes)) }
+		"sha256" => { HEXLOWER.encode(&Sha256::digest(bytes)) }
+		"sha512" => { HEXLOWER.encode(&Sha512::digest(bytes)) }
+		_ => { panic!("Unknown checksum extension {extension}") }
+	}
+}
+
+// Writes every checksum file for the bytes stored at key
+pub async fn write_checksums(s3_client: &Client, maven_config: &MavenConfig, key: &str, bytes: &[u8]) -> Result<(), Error> {
+	for extension in CHECKSUM_EXTENSIONS {
+		s3_client.put_object()
+			.bucket(&maven_config.bucket_name)
+			.key(format!("{key}.{extension}"))
+			.content_type("text/plain")
+			.body(ByteStream::from(digest(extension, bytes).into_bytes()))
+			.send().await?;
+	}
+	Ok(())
+}
+
+// Reads the object at key back out of s3 and regenerates its checksums
+pub async fn regenerate(s3_client: &Client, maven_config: &MavenConfig, key: &str) -> Result<(), Error> {
+	let obj = s3_client.get_object()
+		.bucket(&maven_config.bucket_name)
+		.key(key)
+		.send().await?;
+	let bytes = obj.body.collect().await?.into_bytes();
+
+	tracing::info!("Regenerating checksums for \"{key}\"");
+	write_checksums(s3_client, maven_config, key, &bytes).await
+}
+
+// Rewrites only the checksums that don't match what is stored at key right now, nothing when it's gone
+// Metadata is written by us and by deploying clients alike, so its checksums can end up describing a copy that was replaced
+pub async fn verify(s3_client: &Client, maven_config: &MavenConfig, key: &str) -> Result<(), Error> {
+	let obj = match storage::get_resource(s3_client, maven_config, key).await {
+		None => { return Ok(()) }
+		Some(obj) => { obj }
+	};
+	let bytes = obj.body.collect().await?.into_bytes();
+
+	for extension in CHECKSUM_EXTENSIONS {
+		let checksum_key = format!("{key}.{extension}");
+		let expected = digest(extension, &bytes);
+		// some clients put the file name after the hash
+		let stored = storage::get_object_text(s3_client, &maven_config.bucket_name, &checksum_key).await;
+		if stored.as_deref().and_then(|it| { it.split_whitespace().next() }) == Some(expected.as_str()) {
+			continue
+		}
+
+		tracing::info!("\"{checksum_key}\" doesn't match \"{key}\", rewriting it");
+		s3_client.put_object()
+			.bucket(&maven_config.bucket_name)
+			.key(&checksum_key)
+			.content_type("text/plain")
+			.body(ByteStream::from(expected.into_bytes()))
+			.send().await?;
+	}
+	Ok(())
+}
+
+// Removes the checksum files left behind by a deleted object
+pub async fn remove(s3_client: &Client, maven_config: &MavenConfig, key: &str) -> Result<(), Error> {
+	tracing::info!("Removing checksums for \"{key}\"");
+	for extension in CHECKSUM_EXTENSIONS {
+		s3_client.delete_object()
+			.bucket(&maven_config.bucket_name)
+			.key(format!("{key}.{extension}"))
+			.send().await?;
+	}
+	Ok(())
+}
@@ -1,12 +1,34 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use crate::maven::version::compare_versions;
 
 // Acts like a tree like structure, where each layer has a named list of child layers
 // Important to note you cant move back up the tree, the full path is usually passed along when required
 pub struct Layer {
 	children: HashMap<String, Box<Layer>>,
 
-	pub packages: Vec<String>,
-	pub files: Vec<String>
+	pub packages: Vec<LayerPackage>,
+	pub files: Vec<LayerFile>
+}
+
+#[derive(Clone, Copy)]
+pub enum LayerSort {
+	Name,
+	Date
+}
+
+#[derive(Clone)]
+pub struct LayerPackage {
+	pub name: String,
+	// Newest file we've seen anywhere under this package, unknown if we only ever listed the prefix
+	pub last_modified: Option<i64>
+}
+
+#[derive(Clone)]
+pub struct LayerFile {
+	pub name: String,
+	pub size: i64,
+	pub last_modified: i64
 }
 
 impl Layer {
@@ -24,7 +46,7 @@ impl Layer {
 		}
 
 		let has_child = self.children.contains_key(ids[index]);
-		return if has_child {
+		if has_child {
 			self.children
 				.get(ids[index])
 				.expect("Failed to get child after just checking it exists")
@@ -35,7 +57,7 @@ impl Layer {
 	}
 
 	fn get_or_compute_layer(&mut self, id: &str) -> &mut Layer {
-		return self.children.entry(String::from(id))
+		self.children.entry(String::from(id))
 			.or_insert(Box::from(Layer::new()))
 	}
 
@@ -47,19 +69,63 @@ impl Layer {
 		let child = self.children
 			.get(ids[index])
 			.unwrap_or_else(|| panic!("Call to descent requested id that does not exist in the path, was looking for {:?}", ids));
-		return child.descend(ids, index + 1)
+		child.descend(ids, index + 1)
 	}
 
-	pub fn populate(&mut self, ids: &Vec<&str>, index: usize) -> &mut Layer {
+	// Creates every layer along the path, bumping the modified time of each package on the way if we know it
+	pub fn populate(&mut self, ids: &Vec<&str>, index: usize, last_modified: Option<i64>) -> &mut Layer {
 		if index >= ids.len() {
 			return self
 		}
 
-		self.packages.push(String::from(ids[index]));
-		self.packages.dedup();
+		match self.packages.iter_mut().find(|it| { it.name == ids[index] }) {
+			None => {
+				self.packages.push(LayerPackage {
+					name: String::from(ids[index]),
+					last_modified
+				})
+			}
+			Some(package) => {
+				package.last_modified = package.last_modified.max(last_modified);
+			}
+		}
 
 		let child = self.get_or_compute_layer(ids[index]);
-		return child.populate(ids, index + 1)
+		child.populate(ids, index + 1, last_modified)
+	}
+
+	// Sorts only this layer, children are sorted when they get listed themselves
+	// By name, version directories go in version order so 1.10 comes after 1.9, by date the newest is first
+	pub fn sort(&mut self, sort: LayerSort) {
+		match sort {
+			LayerSort::Name => {
+				self.packages.sort_by(|a, b| { compare_names(&a.name, &b.name) });
+				self.files.sort_by(|a, b| { a.name.cmp(&b.name) });
+			}
+			LayerSort::Date => {
+				self.packages.sort_by(|a, b| { b.last_modified.cmp(&a.last_modified).then_with(|| { compare_names(&a.name, &b.name) }) });
+				self.files.sort_by(|a, b| { b.last_modified.cmp(&a.last_modified).then_with(|| { a.name.cmp(&b.name) }) });
+			}
+		}
+	}
+
+	pub fn add_file(&mut self, file: LayerFile) {
+		match self.files.iter_mut().find(|it| { it.name == file.name }) {
+			None => { self.files.push(file) }
+			Some(existing) => { *existing = file }
+		}
+	}
+}
+
+fn looks_like_version(name: &str) -> bool {
+	name.starts_with(|it: char| { it.is_ascii_digit() })
+}
+
+fn compare_names(a: &str, b: &str) -> Ordering {
+	if looks_like_version(a) && looks_like_version(b) {
+		compare_versions(a, b)
+	} else {
+		a.cmp(b)
 	}
 }
 
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{ByteStream, DateTime, DateTimeFormat};
+use lambda_runtime::Error;
+use crate::cfg::MavenConfig;
+use crate::maven::version::compare_versions;
+use crate::storage;
+use crate::storage::checksums;
+use crate::util::escape_xml;
+
+pub const METADATA_FILE: &str = "maven-metadata.xml";
+
+// Checks if a key is a metadata file (or a checksum of one), these are never treated as versions
+pub fn is_metadata(key: &str) -> bool {
+	let file_name = key.rsplit_once('/').map(|it| { it.1 }).unwrap_or(key);
+	file_name.starts_with("maven-metadata")
+}
+
+// Rebuilds the maven-metadata.xml (and its checksums) for an artifact from what is actually in the bucket
+// Versions are in maven order, so a 1.2.9 backport uploaded after 1.3.0 doesn't become "latest"
+pub async fn regenerate(s3_client: &Client, maven_config: &MavenConfig, artifact_directory: &str) -> Result<(), Error> {
+	let metadata_key = format!("{artifact_directory}/{METADATA_FILE}");
+	let versions = list_versions(s3_client, maven_config, artifact_directory).await?;
+
+	if versions.is_empty() {
+		tracing::info!("No versions left under \"{artifact_directory}\", removing metadata");
+		s3_client.delete_object()
+			.bucket(&maven_config.bucket_name)
+			.key(&metadata_key)
+			.send().await?;
+		return checksums::remove(s3_client, maven_config, &metadata_key).await
+	}
+
+	let mut ordered: Vec<String> = versions.into_keys().collect();
+	ordered.sort_by(|a, b| { compare_versions(a, b).then_with(|| { a.cmp(b) }) });
+
+	let (group_path, artifact_id) = artifact_directory.rsplit_once('/')
+		.expect("Artifact directory must contain a group");
+	let xml = build_metadata(&group_path.replace('/', "."), artifact_id, &ordered);
+
+	tracing::info!("Regenerating metadata for \"{artifact_directory}\" with {} versions", ordered.len());
+	s3_client.put_object()
+		.bucket(&maven_config.bucket_name)
+		.key(&metadata_key)
+		.content_type("application/xml")
+		.body(ByteStream::from(xml.clone().into_bytes()))
+		.send().await?;
+	checksums::write_checksums(s3_client, maven_config, &metadata_key, xml.as_bytes()).await
+}
+
+// Every version directory of an artifact that actually holds something, with the time it was last changed
+pub async fn list_versions(s3_client: &Client, maven_config: &MavenConfig, artifact_directory: &str) -> Result<HashMap<String, i64>, Error> {
+	let prefix = format!("{artifact_directory}/");
+	let objects = storage::list_objects(s3_client, maven_config, &prefix).await?;
+
+	let mut versions: HashMap<String, i64> = HashMap::new();
+	for obj in objects {
+		let key = obj.key.unwrap_or_default();
+		let (version, file) = match key[prefix.len()..].split_once('/') {
+			None => { continue }
+			Some(split) => { split }
+		};
+
+		if file.is_empty() || file.contains('/') || checksums::is_checksum(file) || is_metadata(file) {
+			continue
+		}
+
+		let modified = obj.last_modified.map(|it| { it.secs() }).unwrap_or(0);
+		let entry = versions.entry(String::from(version)).or_insert(modified);
+		*entry = (*entry).max(modified);
+	}
+	Ok(versions)
+}
+
+// Pulls the version list out of an artifact level maven-metadata.xml
+pub fn parse_versions(xml: &str) -> Option<Vec<String>> {
+	let document = roxmltree::Document::parse(xml).ok()?;
+	let versions = document.descendants()
+		.filter(|it| { it.has_tag_name("version") && it.parent().is_some_and(|parent| { parent.has_tag_name("versions") }) })
+		.filter_map(|it| { it.text() })
+		.map(|it| { String::from(it.trim()) })
+		.collect();
+	Some(versions)
+}
+
+// Finds the timestamped file name a snapshot was deployed under from a version level maven-metadata.xml
+// Returns the "value" part, so "1.0-20231012.120000-1" for lib-1.0-20231012.120000-1.jar
+pub fn parse_snapshot_value(xml: &str, classifier: Option<&str>, extension: &str) -> Option<String> {
+	let document = roxmltree::Document::parse(xml).ok()?;
+	document.descendants()
+		.filter(|it| { it.has_tag_name("snapshotVersion") })
+		.find(|it| { child_text(*it, "classifier") == classifier && child_text(*it, "extension") == Some(extension) })
+		.and_then(|it| { child_text(it, "value") })
+		.map(String::from)
+}
+
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+	node.children()
+		.find(|it| { it.has_tag_name(name) })
+		.and_then(|it| { it.text() })
+		.map(|it| { it.trim() })
+}
+
+// Versions are expected in maven order, lowest first
+fn build_metadata(group_id: &str, artifact_id: &str, versions: &[String]) -> String {
+	let latest = versions.last().map(|it| { it.as_str() }).unwrap_or("");
+	let release = versions.iter().rev()
+		.find(|it| { !it.ends_with("-SNAPSHOT") });
+
+	let mut builder = string_builder::Builder::new(512);
+	builder.append("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+	builder.append("<metadata>\n");
+	builder.append(format!("  <groupId>{}</groupId>\n", escape_xml(group_id)));
+	builder.append(format!("  <artifactId>{}</artifactId>\n", escape_xml(artifact_id)));
+	builder.append("  <versioning>\n");
+	builder.append(format!("    <latest>{}</latest>\n", escape_xml(latest)));
+	if let Some(release) = release {
+		builder.append(format!("    <release>{}</release>\n", escape_xml(release)));
+	}
+	builder.append("    <versions>\n");
+	for version in versions {
+		builder.append(format!("      <version>{}</version>\n", escape_xml(version)));
+	}
+	builder.append("    </versions>\n");
+	builder.append(format!("    <lastUpdated>{}</lastUpdated>\n", last_updated()));
+	builder.append("  </versioning>\n");
+	builder.append("</metadata>\n");
+	builder.string().unwrap()
+}
+
+// Maven wants yyyyMMddHHmmss in UTC, which is just the digits of an RFC 3339 timestamp
+fn last_updated() -> String {
+	let now = DateTime::from(SystemTime::now())
+		.fmt(DateTimeFormat::DateTime)
+		.expect("Failed to format the current time");
+	now.chars().filter(|it| { it.is_ascii_digit() }).take(14).collect()
+}
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::time::SystemTime;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{ByteStream, DateTime, DateTimeFormat};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use lambda_runtime::Error;
+use crate::cfg::{MavenConfig, PrivatePaths};
+use crate::maven::layout;
+use crate::maven::layout::MavenPath;
+use crate::storage::checksums;
+use crate::storage::repository_index::RepositoryIndex;
+
+// Where IDEs expect to find the index, relative to the repository root
+pub const INDEX_PREFIX: &str = ".index/";
+pub const INDEX_FILE: &str = ".index/nexus-maven-repository-index.gz";
+pub const PROPERTIES_FILE: &str = ".index/nexus-maven-repository-index.properties";
+
+// Field flags from the maven indexer data format
+const INDEXED: u8 = 1;
+const TOKENIZED: u8 = 2;
+const STORED: u8 = 4;
+
+pub fn is_index_file(key: &str) -> bool {
+	key.starts_with(INDEX_PREFIX)
+}
+
+// One file of a version, "lib-1.0-sources.jar" is the sources classifier with a jar extension
+struct ArtifactFile {
+	classifier: Option<String>,
+	extension: String,
+	// only known for the pom of the version
+	packaging: Option<String>,
+	size: i64,
+	last_modified: i64
+}
+
+// Writes a full (non incremental) maven indexer export of the repository, IntelliJ and Eclipse read this for
+// dependency completion against remote repositories
+// Checksums of each artifact are left out, they are optional and would mean reading a .sha1 per artifact
+pub async fn rebuild(s3_client: &Client, maven_config: &MavenConfig, index: &RepositoryIndex) -> Result<(), Error> {
+	let artifacts = collect_artifacts(index, &maven_config.private_paths);
+	let now = DateTime::from(SystemTime::now());
+	let timestamp_millis = now.secs() * 1000 + now.subsec_nanos() as i64 / 1_000_000;
+
+	let mut writer = IndexWriter::new();
+	writer.header(timestamp_millis)?;
+	writer.document(&[
+		(STORED, "DESCRIPTOR", String::from("NexusIndex")),
+		(STORED, "IDXINFO", format!("1.0|{}", maven_config.indexer_repository_id))
+	])?;
+
+	let mut groups: BTreeSet<String> = BTreeSet::new();
+	let mut document_count = 0;
+	for ((group_id, artifact_id, version), files) in &artifacts {
+		groups.insert(group_id.clone());
+
+		// the packaging of the version, from its pom, rather than the extension of any one file
+		let packaging = files.iter()
+			.find_map(|it| { it.packaging.as_deref() })
+			.unwrap_or("jar");
+
+		let has_main = files.iter().any(|it| { it.classifier.is_none() && it.extension != "pom" });
+		let exists = |classifier: &str| { if files.iter().any(|it| { it.classifier.as_deref() == Some(classifier) }) { "1" } else { "0" } };
+
+		for file in files {
+			// a pom next to a jar just describes the jar
+			if file.extension == "pom" && file.classifier.is_none() && has_main {
+				continue
+			}
+
+			let classifier = file.classifier.as_deref().unwrap_or("NA");
+			let signed = index.files.contains_key(&format!(
+				"{}/{artifact_id}/{version}/{artifact_id}-{version}{}.{}.asc",
+				group_id.replace('.', "/"),
+				file.classifier.as_ref().map(|it| { format!("-{it}") }).unwrap_or_default(),
+				file.extension
+			));
+
+			writer.document(&[
+				(INDEXED | STORED, "u", format!("{group_id}|{artifact_id}|{version}|{classifier}|{}", file.extension)),
+				(STORED, "i", format!(
+					"{packaging}|{}|{}|{}|{}|{}|{}",
+					file.last_modified * 1000,
+					file.size,
+					exists("sources"),
+					exists("javadoc"),
+					if signed { "1" } else { "0" },
+					file.extension
+				)),
+				(STORED, "m", (file.last_modified * 1000).to_string()),
+				(INDEXED | TOKENIZED | STORED, "n", artifact_id.clone())
+			])?;
+			document_count += 1;
+		}
+	}
+
+	let root_groups: BTreeSet<&str> = groups.iter().map(|it| { it.split('.').next().unwrap_or(it) }).collect();
+	writer.document(&[
+		(INDEXED | STORED, "allGroups", String::from("allGroups")),
+		(STORED, "allGroupsList", groups.iter().map(String::as_str).collect::<Vec<&str>>().join("|"))
+	])?;
+	writer.document(&[
+		(INDEXED | STORED, "rootGroups", String::from("rootGroups")),
+		(STORED, "rootGroupsList", root_groups.into_iter().collect::<Vec<&str>>().join("|"))
+	])?;
+
+	let compressed = writer.finish()?;
+	tracing::info!("Writing maven indexer export with {document_count} documents");
+	s3_client.put_object()
+		.bucket(&maven_config.bucket_name)
+		.key(INDEX_FILE)
+		.content_type("application/gzip")
+		.body(ByteStream::from(compressed.clone()))
+		.send().await?;
+	checksums::write_checksums(s3_client, maven_config, INDEX_FILE, &compressed).await?;
+
+	let properties = build_properties(maven_config, &now);
+	s3_client.put_object()
+		.bucket(&maven_config.bucket_name)
+		.key(PROPERTIES_FILE)
+		.content_type("text/plain")
+		.body(ByteStream::from(properties.into_bytes()))
+		.send().await?;
+	Ok(())
+}
+
+// Groups every artifact file in the index by its coordinates
+// The export is served to anyone, so private artifacts are left out of it
+// Unless the whole repository is private, then nobody anonymous can read the export anyway
+fn collect_artifacts(index: &RepositoryIndex, private_paths: &PrivatePaths) -> BTreeMap<(String, String, String), Vec<ArtifactFile>> {
+	let mut artifacts: BTreeMap<(String, String, String), Vec<ArtifactFile>> = BTreeMap::new();
+
+	for (key, file) in &index.files {
+		if !private_paths.everything && private_paths.is_private(key) {
+			continue
+		}
+
+		let artifact = match layout::parse(key) {
+			Some(MavenPath::Artifact(artifact)) if artifact.checksum.is_none() && !artifact.is_signature() => { artifact }
+			_ => { continue }
+		};
+
+		artifacts.entry((artifact.group_id, artifact.artifact_id, artifact.version))
+			.or_default()
+			.push(ArtifactFile {
+				classifier: artifact.classifier,
+				extension: artifact.extension,
+				packaging: file.packaging.clone(),
+				size: file.size,
+				last_modified: file.last_modified
+			});
+	}
+	artifacts
+}
+
+fn build_properties(maven_config: &MavenConfig, now: &DateTime) -> String {
+	// yyyyMMddHHmmss.SSS Z, always in UTC for us
+	let rfc3339 = now.fmt(DateTimeFormat::DateTime).expect("Failed to format the current time");
+	let digits: String = rfc3339.chars().filter(|it| { it.is_ascii_digit() }).collect();
+	let millis = format!("{:03}", now.subsec_nanos() / 1_000_000);
+	let timestamp = format!("{}.{millis} +0000", &digits[..14]);
+
+	let mut builder = string_builder::Builder::new(256);
+	builder.append(format!("nexus.index.id={}\n", maven_config.indexer_repository_id));
+	builder.append(format!("nexus.index.chain-id={}\n", now.secs()));
+	builder.append(format!("nexus.index.timestamp={timestamp}\n"));
+	builder.string().unwrap()
+}
+
+// Java DataOutputStream style writer for the indexer format, gzipped as it goes
+struct IndexWriter {
+	encoder: GzEncoder<Vec<u8>>
+}
+
+impl IndexWriter {
+	fn new() -> IndexWriter {
+		IndexWriter {
+			encoder: GzEncoder::new(Vec::new(), Compression::default())
+		}
+	}
+
+	fn header(&mut self, timestamp_millis: i64) -> std::io::Result<()> {
+		self.encoder.write_all(&[1])?;
+		self.encoder.write_all(&timestamp_millis.to_be_bytes())
+	}
+
+	fn document(&mut self, fields: &[(u8, &str, String)]) -> std::io::Result<()> {
+		self.encoder.write_all(&(fields.len() as i32).to_be_bytes())?;
+		for (flags, name, value) in fields {
+			self.encoder.write_all(&[*flags])?;
+
+			// names use writeUTF with a short length, values an int length so they can be long
+			let name = modified_utf8(name);
+			self.encoder.write_all(&(name.len() as u16).to_be_bytes())?;
+			self.encoder.write_all(&name)?;
+
+			let value = modified_utf8(value);
+			self.encoder.write_all(&(value.len() as i32).to_be_bytes())?;
+			self.encoder.write_all(&value)?;
+		}
+		Ok(())
+	}
+
+	fn finish(self) -> std::io::Result<Vec<u8>> {
+		self.encoder.finish()
+	}
+}
+
+// Java's "modified" utf-8, which encodes nul as two bytes and everything outside the BMP as surrogate pairs
+fn modified_utf8(value: &str) -> Vec<u8> {
+	let mut bytes = Vec::with_capacity(value.len());
+	for unit in value.encode_utf16() {
+		match unit {
+			0x0001..=0x007F => { bytes.push(unit as u8) }
+			0x0000 | 0x0080..=0x07FF => {
+				bytes.push((0xC0 | (unit >> 6)) as u8);
+				bytes.push((0x80 | (unit & 0x3F)) as u8);
+			}
+			_ => {
+				bytes.push((0xE0 | (unit >> 12)) as u8);
+				bytes.push((0x80 | ((unit >> 6) & 0x3F)) as u8);
+				bytes.push((0x80 | (unit & 0x3F)) as u8);
+			}
+		}
+	}
+	bytes
+}
@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::SystemTime;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{ByteStream, DateTime};
+use aws_sdk_s3::types::Object;
+use lambda_runtime::Error;
+use serde::{Deserialize, Serialize};
+use crate::cfg::MavenConfig;
+use crate::maven::layout;
+use crate::maven::layout::MavenPath;
+use crate::maven::pom::Pom;
+use crate::storage;
+
+pub const INDEX_KEY: &str = ".maven/index.json";
+
+// A flat listing of everything in the bucket, persisted so other invocations dont have to walk the whole bucket
+#[derive(Serialize, Deserialize, Default)]
+pub struct RepositoryIndex {
+	pub generated: i64,
+	pub files: BTreeMap<String, IndexedFile>
+}
+
+impl RepositoryIndex {
+	// Whether anything is stored under "com/example/lib/", keys are sorted so this is a single lookup
+	pub fn contains_directory(&self, directory: &str) -> bool {
+		let prefix = format!("{}/", directory.trim_end_matches('/'));
+		self.files.range(prefix.clone()..).next().is_some_and(|(key, _)| { key.starts_with(&prefix) })
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexedFile {
+	pub size: i64,
+	pub last_modified: i64,
+	// the <packaging> of a version's own pom, kept here so the indexer export doesn't have to read every pom again
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub packaging: Option<String>
+}
+
+// Loads the persisted index, if one has been generated yet
+pub async fn load(s3_client: &Client, maven_config: &MavenConfig) -> Option<RepositoryIndex> {
+	let obj = s3_client.get_object()
+		.bucket(&maven_config.bucket_name)
+		.key(INDEX_KEY)
+		.send().await.ok()?;
+	let bytes = obj.body.collect().await.ok()?.into_bytes();
+
+	match serde_json::from_slice(&bytes) {
+		Ok(index) => { Some(index) }
+		Err(err) => {
+			tracing::warn!("Persisted index is unreadable, ignoring it. {err}");
+			None
+		}
+	}
+}
+
+// Rebuilds the index from a full listing instead of patching the old one
+// Only for when there is no index yet or it has drifted, events update it a directory at a time
+pub async fn rebuild(s3_client: &Client, maven_config: &MavenConfig) -> Result<RepositoryIndex, Error> {
+	let objects = storage::list_objects(s3_client, maven_config, "").await?;
+	// poms that didn't change keep the packaging read from them last time
+	let previous = load(s3_client, maven_config).await.map(|it| { it.files }).unwrap_or_default();
+
+	let mut index = RepositoryIndex::default();
+	for obj in objects {
+		insert(&mut index, obj, &previous);
+	}
+	fill_packaging(s3_client, maven_config, &mut index, "").await;
+
+	persist(s3_client, maven_config, &mut index).await?;
+	Ok(index)
+}
+
+// Relists only the directories that changed, like "com/example/lib/1.0", and swaps their files in the persisted index
+// Events for a bulk sync arrive concurrently, so one batch can overwrite what another just wrote. A directory that lost
+// its update catches up with the next change in it, or with a full rebuild
+pub async fn update(s3_client: &Client, maven_config: &MavenConfig, directories: &BTreeSet<String>) -> Result<RepositoryIndex, Error> {
+	let mut index = match load(s3_client, maven_config).await {
+		Some(index) => { index }
+		None => {
+			tracing::info!("No index to update yet, building it from a full listing");
+			return rebuild(s3_client, maven_config).await
+		}
+	};
+
+	for directory in directories {
+		let prefix = if directory.is_empty() { String::new() } else { format!("{directory}/") };
+		let mut previous = BTreeMap::new();
+		index.files.retain(|key, file| {
+			if !is_directly_in(key, &prefix) {
+				return true
+			}
+			previous.insert(key.clone(), file.clone());
+			false
+		});
+
+		for obj in storage::list_files(s3_client, maven_config, &prefix).await? {
+			insert(&mut index, obj, &previous);
+		}
+		fill_packaging(s3_client, maven_config, &mut index, &prefix).await;
+	}
+
+	persist(s3_client, maven_config, &mut index).await?;
+	Ok(index)
+}
+
+// "com/example/lib/1.0/lib-1.0.jar" is directly in "com/example/lib/1.0/", anything further down isn't
+fn is_directly_in(key: &str, prefix: &str) -> bool {
+	key.strip_prefix(prefix).is_some_and(|it| { !it.contains('/') })
+}
+
+fn insert(index: &mut RepositoryIndex, obj: Object, previous: &BTreeMap<String, IndexedFile>) {
+	let key = obj.key.unwrap_or_default();
+	if storage::is_internal(&key) {
+		return
+	}
+
+	let size = obj.size;
+	let last_modified = obj.last_modified.map(|it| { it.secs() }).unwrap_or(0);
+	let packaging = previous.get(&key)
+		.filter(|it| { it.size == size && it.last_modified == last_modified })
+		.and_then(|it| { it.packaging.clone() });
+	index.files.insert(key, IndexedFile { size, last_modified, packaging });
+}
+
+// Reads the packaging of every version pom under the prefix that we don't know it for yet
+// A pom that can't be parsed counts as "jar" like one without <packaging>, one that can't be read is tried again next time
+async fn fill_packaging(s3_client: &Client, maven_config: &MavenConfig, index: &mut RepositoryIndex, prefix: &str) {
+	let missing: Vec<String> = index.files.iter()
+		.filter(|(key, file)| { key.starts_with(prefix) && file.packaging.is_none() && is_version_pom(key) })
+		.map(|(key, _)| { key.clone() })
+		.collect();
+
+	for key in missing {
+		let source = match storage::get_object_text(s3_client, &maven_config.bucket_name, &key).await {
+			None => { continue }
+			Some(source) => { source }
+		};
+		let packaging = Pom::parse(&source).map(|it| { it.packaging }).unwrap_or(String::from("jar"));
+		if let Some(file) = index.files.get_mut(&key) {
+			file.packaging = Some(packaging);
+		}
+	}
+}
+
+// The pom describing a version, not a checksum or signature of it or one with a classifier
+fn is_version_pom(key: &str) -> bool {
+	match layout::parse(key) {
+		Some(MavenPath::Artifact(artifact)) => { artifact.extension == "pom" && artifact.classifier.is_none() && artifact.checksum.is_none() }
+		_ => { false }
+	}
+}
+
+async fn persist(s3_client: &Client, maven_config: &MavenConfig, index: &mut RepositoryIndex) -> Result<(), Error> {
+	index.generated = DateTime::from(SystemTime::now()).secs();
+
+	tracing::info!("Persisting index with {} files", index.files.len());
+	s3_client.put_object()
+		.bucket(&maven_config.bucket_name)
+		.key(INDEX_KEY)
+		.content_type("application/json")
+		.body(ByteStream::from(serde_json::to_vec(index)?))
+		.send().await?;
+	Ok(())
+}
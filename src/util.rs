@@ -1,30 +1,64 @@
-use lambda_http::{Body, Response};
+use std::time::SystemTime;
+use aws_sdk_s3::primitives::DateTime;
+use lambda_http::aws_lambda_events::apigw::ApiGatewayV2httpResponse;
+use http::header::SET_COOKIE;
+use lambda_http::{Body, Request, RequestExt, Response};
+use lambda_http::request::RequestContext;
 use lambda_runtime::Error;
-use once_cell::sync::Lazy;
-use regex::Regex;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use crate::cfg::MavenConfig;
+use crate::maven::layout;
+use crate::maven::layout::{ArtifactPath, MavenPath, MetadataPath};
+use crate::storage;
+use crate::storage::layers::LayerSort;
 
-// Checks if a path seems to be a request for a file, by checking if the end of the path is an extension
-pub fn is_file_request(haystack: &str) -> bool {
-	static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r".+\.(pom|jar|\w+)").unwrap());
-	!haystack.ends_with('/') && RE.is_match(haystack)
+// Checks if the client asked for json, either with ?format=json or by only accepting json
+pub fn wants_json(request: &Request) -> bool {
+	if let Some(format) = request.query_string_parameters().first("format") {
+		return format == "json"
+	}
+
+	match request.headers().get("Accept").and_then(|it| { it.to_str().ok() }) {
+		None => { false }
+		Some(accept) => { accept.contains("application/json") && !accept.contains("text/html") }
+	}
+}
+
+// A query parameter with the whitespace around it trimmed, None when it's missing or empty
+pub fn query_param(request: &Request, name: &str) -> Option<String> {
+	request.query_string_parameters().first(name)
+		.map(|it| { String::from(it.trim()) })
+		.filter(|it| { !it.is_empty() })
+}
+
+// ?sort=date lists the newest first, anything else sorts by name
+pub fn index_sort(request: &Request) -> LayerSort {
+	match request.query_string_parameters().first("sort") {
+		Some("date") => { LayerSort::Date }
+		_ => { LayerSort::Name }
+	}
 }
 
 // Gets the mime type of the file name
 // Cant just use the mime-type crate because it doesnt support `pom` as xml
 // Could probably set it up as a backup but this is a maven, not a file host
 pub fn mime_type(resource_path: &str) -> String {
-	let split = resource_path.rsplit_once('.');
-	match split {
-		None => { String::from("text/plain") }
-		Some(splice) => {
-			let postfix = splice.1;
-
-			match postfix {
-				"jar" => { String::from("application/java-archive") }
-				"xml" | "pom" => { String::from("application/xml") }
-				_ => { String::from("text/plain") }
-			}
-		}
+	let file_type = match layout::parse(resource_path) {
+		// checksums are plain text, whatever they are a checksum of
+		Some(MavenPath::Artifact(ArtifactPath { checksum: Some(_), .. })) => { String::from("txt") }
+		Some(MavenPath::Metadata(MetadataPath { checksum: Some(_), .. })) => { String::from("txt") }
+		Some(MavenPath::Artifact(artifact)) => { artifact.extension }
+		Some(MavenPath::Metadata(_)) => { String::from("xml") }
+		None => { String::from(resource_path) }
+	};
+
+	match file_type.rsplit('.').next().unwrap_or_default() {
+		"jar" | "war" | "aar" => { String::from("application/java-archive") }
+		"xml" | "pom" => { String::from("application/xml") }
+		"module" => { String::from("application/json") }
+		"asc" => { String::from("application/pgp-signature") }
+		"gz" => { String::from("application/gzip") }
+		_ => { String::from("text/plain") }
 	}
 }
 
@@ -44,4 +78,144 @@ pub fn simple_response_fmt(status_code: u16, msg: String) -> Result<Response<Bod
 		.body(Body::Text(msg))
 		.map_err(Box::new)?;
 	Ok(resp)
-}
\ No newline at end of file
+}
+
+// The url the repository is reachable at, whatever comes before the request path in this request
+// "https://maven.example.com/prod/" for "/prod/com/example" or just the host behind a custom domain
+// The host is the domain the gateway was called on, never the Host header, pages with this in them end up in shared caches
+pub fn base_url(request: &Request, maven_config: &MavenConfig, request_path: &str) -> String {
+	if let Some(public_url) = &maven_config.public_url {
+		return public_url.clone()
+	}
+
+	let host = match request.request_context() {
+		RequestContext::ApiGatewayV2(context) => { context.domain_name }
+		_ => { None }
+	}.unwrap_or(String::from("localhost"));
+	let raw_path = request.raw_http_path();
+	let prefix = raw_path.strip_suffix(request_path).unwrap_or("/");
+	format!("https://{host}{}/", prefix.trim_end_matches('/'))
+}
+
+// Decodes the path from the request and checks it can't turn into a surprising s3 key
+// None for "..", "." and empty segments, encoded slashes and control characters. A trailing slash is kept, it means a directory
+pub fn canonical_path(raw_path: &str) -> Option<String> {
+	let lowercase = raw_path.to_ascii_lowercase();
+	if lowercase.contains("%2f") || lowercase.contains("%5c") {
+		return None
+	}
+
+	let path = percent_decode_str(raw_path).decode_utf8().ok()?;
+	if path.starts_with('/') || path.chars().any(|it| { it.is_control() || it == '\\' }) {
+		return None
+	}
+
+	let directory = path.strip_suffix('/').unwrap_or(&path);
+	if !directory.is_empty() && directory.split('/').any(|it| { it.is_empty() || it == "." || it == ".." }) {
+		return None
+	}
+	Some(path.into_owned())
+}
+
+// A directory put together from query parameters or part of the path, like "com/example/lib" for /api/latest
+// None unless canonical_path would take it as it is, and for our own bookkeeping, the same as requests for those paths get
+pub fn artifact_directory(path: &str) -> Option<String> {
+	let directory = canonical_path(path).filter(|it| { it == path && !it.ends_with('/') })?;
+	if directory.is_empty() || storage::is_internal(&directory) {
+		return None
+	}
+	Some(directory)
+}
+
+// Everything but the unreserved characters, so a name can't change which path or query a link points at
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+pub fn encode_path_segment(segment: &str) -> String {
+	utf8_percent_encode(segment, PATH_SEGMENT).to_string()
+}
+
+// Encodes each segment of a path but keeps the slashes between them
+pub fn encode_path(path: &str) -> String {
+	path.split('/').map(encode_path_segment).collect::<Vec<String>>().join("/")
+}
+
+// Escapes the handful of characters that would break out of xml text or attribute values
+pub fn escape_xml(value: &str) -> String {
+	let mut escaped = String::with_capacity(value.len());
+	for character in value.chars() {
+		match character {
+			'&' => { escaped.push_str("&amp;") }
+			'<' => { escaped.push_str("&lt;") }
+			'>' => { escaped.push_str("&gt;") }
+			'"' => { escaped.push_str("&quot;") }
+			'\'' => { escaped.push_str("&#39;") }
+			_ => { escaped.push(character) }
+		}
+	}
+	escaped
+}
+
+// Does what lambda_http would do to hand a response back to the v2 gateway
+// We have to do it ourselves now that the same function also receives s3 events
+pub fn into_gateway_response(response: Response<Body>) -> ApiGatewayV2httpResponse {
+	let (parts, body) = response.into_parts();
+	let (is_base64_encoded, body) = match body {
+		Body::Empty => { (false, None) }
+		Body::Text(_) => { (false, Some(body)) }
+		Body::Binary(_) => { (true, Some(body)) }
+	};
+
+	// the gateway wants cookies in their own field
+	let mut headers = parts.headers;
+	let cookies = headers.get_all(SET_COOKIE).iter()
+		.map(|it| { String::from(it.to_str().unwrap_or_default()) })
+		.collect();
+	headers.remove(SET_COOKIE);
+
+	ApiGatewayV2httpResponse {
+		status_code: parts.status.as_u16() as i64,
+		headers: headers.clone(),
+		multi_value_headers: headers,
+		body,
+		is_base64_encoded,
+		cookies
+	}
+}
+
+// Seconds since the epoch, what everything we store times as uses
+pub fn now() -> i64 {
+	DateTime::from(SystemTime::now()).secs()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decodes_plain_paths() {
+		assert_eq!(canonical_path("com/example/lib/1.0/lib-1.0.jar").as_deref(), Some("com/example/lib/1.0/lib-1.0.jar"));
+		assert_eq!(canonical_path("com/example/my%20lib/").as_deref(), Some("com/example/my lib/"));
+		assert_eq!(canonical_path("").as_deref(), Some(""));
+	}
+
+	#[test]
+	fn refuses_traversal() {
+		for path in ["..", "../secret", "com/../../secret", "com/./lib", "com/%2e%2e/secret", "com//lib", "com/lib//"] {
+			assert_eq!(canonical_path(path), None, "{path} should be refused");
+		}
+	}
+
+	#[test]
+	fn refuses_encoded_separators() {
+		for path in ["com%2fexample", "com%2Fexample", "com%5cexample", "com%5Cexample", "com\\example"] {
+			assert_eq!(canonical_path(path), None, "{path} should be refused");
+		}
+	}
+
+	#[test]
+	fn refuses_absolute_paths_and_control_characters() {
+		for path in ["/com/example", "%2Fcom/example", "com/example%00.jar", "com/exa\nmple", "com/example%0d%0a", "com/example%7f"] {
+			assert_eq!(canonical_path(path), None, "{path} should be refused");
+		}
+	}
+}